@@ -0,0 +1,348 @@
+use std::collections::{BTreeMap, HashSet};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use bevy::input::InputSystem;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::ron_file::*;
+
+const BINDINGS_FILE_NAME: &str = "bindings.ron";
+
+// ゲームの操作。各systemはキーではなくこれを見る
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Action {
+    MoveLeft,
+    MoveRight,
+    Fire,
+    SwitchWeapon,
+    Restart,
+    Pause,
+    Quit,
+}
+
+impl Action {
+    pub fn all() -> [Self; 7] {
+        [
+            Action::MoveLeft,
+            Action::MoveRight,
+            Action::Fire,
+            Action::SwitchWeapon,
+            Action::Restart,
+            Action::Pause,
+            Action::Quit,
+        ]
+    }
+
+    // 操作設定画面での表示名
+    pub fn label(&self) -> &'static str {
+        match self {
+            Action::MoveLeft => "MOVE LEFT",
+            Action::MoveRight => "MOVE RIGHT",
+            Action::Fire => "FIRE",
+            Action::SwitchWeapon => "WEAPON",
+            Action::Restart => "RESTART",
+            Action::Pause => "PAUSE",
+            Action::Quit => "QUIT",
+        }
+    }
+}
+
+// 操作に割り当てられるキー
+// 設定ファイルにはKeyCodeの名前(ArrowLeft, KeyA, Digit1など)で書く
+#[rustfmt::skip]
+const BINDABLE_KEYS: &[KeyCode] = &[
+    KeyCode::KeyA, KeyCode::KeyB, KeyCode::KeyC, KeyCode::KeyD, KeyCode::KeyE, KeyCode::KeyF,
+    KeyCode::KeyG, KeyCode::KeyH, KeyCode::KeyI, KeyCode::KeyJ, KeyCode::KeyK, KeyCode::KeyL,
+    KeyCode::KeyM, KeyCode::KeyN, KeyCode::KeyO, KeyCode::KeyP, KeyCode::KeyQ, KeyCode::KeyR,
+    KeyCode::KeyS, KeyCode::KeyT, KeyCode::KeyU, KeyCode::KeyV, KeyCode::KeyW, KeyCode::KeyX,
+    KeyCode::KeyY, KeyCode::KeyZ,
+    KeyCode::Digit0, KeyCode::Digit1, KeyCode::Digit2, KeyCode::Digit3, KeyCode::Digit4,
+    KeyCode::Digit5, KeyCode::Digit6, KeyCode::Digit7, KeyCode::Digit8, KeyCode::Digit9,
+    KeyCode::Numpad0, KeyCode::Numpad1, KeyCode::Numpad2, KeyCode::Numpad3, KeyCode::Numpad4,
+    KeyCode::Numpad5, KeyCode::Numpad6, KeyCode::Numpad7, KeyCode::Numpad8, KeyCode::Numpad9,
+    KeyCode::NumpadEnter,
+    KeyCode::ArrowUp, KeyCode::ArrowDown, KeyCode::ArrowLeft, KeyCode::ArrowRight,
+    KeyCode::ShiftLeft, KeyCode::ShiftRight, KeyCode::ControlLeft, KeyCode::ControlRight,
+    KeyCode::AltLeft, KeyCode::AltRight,
+    KeyCode::Space, KeyCode::Enter, KeyCode::Tab, KeyCode::Backspace, KeyCode::Escape,
+    KeyCode::Comma, KeyCode::Period, KeyCode::Slash, KeyCode::Semicolon, KeyCode::Quote,
+    KeyCode::BracketLeft, KeyCode::BracketRight, KeyCode::Backslash, KeyCode::Minus,
+    KeyCode::Equal,
+];
+
+pub fn is_bindable(key: KeyCode) -> bool {
+    BINDABLE_KEYS.contains(&key)
+}
+
+pub fn key_name(key: KeyCode) -> String {
+    format!("{:?}", key)
+}
+
+pub fn parse_key(name: &str) -> Option<KeyCode> {
+    BINDABLE_KEYS
+        .iter()
+        .copied()
+        .find(|key| key_name(*key) == name)
+}
+
+// 画面に出すキーの表示名。KeyAはA、Digit1は1、ArrowLeftはLEFTにする
+pub fn key_label(key: KeyCode) -> String {
+    let name = key_name(key);
+    let name = name
+        .strip_prefix("Key")
+        .or_else(|| name.strip_prefix("Digit"))
+        .or_else(|| name.strip_prefix("Arrow"))
+        .unwrap_or(&name);
+    name.to_ascii_uppercase()
+}
+
+// 操作ごとのキーの割り当て
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyBindings {
+    pub keys: BTreeMap<Action, Vec<KeyCode>>,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        Self {
+            keys: BTreeMap::from([
+                (Action::MoveLeft, vec![KeyCode::ArrowLeft]),
+                (Action::MoveRight, vec![KeyCode::ArrowRight]),
+                (Action::Fire, vec![KeyCode::ShiftLeft, KeyCode::ShiftRight]),
+                (Action::SwitchWeapon, vec![KeyCode::KeyX]),
+                (Action::Restart, vec![KeyCode::Space]),
+                (Action::Pause, vec![KeyCode::KeyP]),
+                (Action::Quit, vec![KeyCode::Escape]),
+            ]),
+        }
+    }
+}
+
+impl KeyBindings {
+    // ファイルがなければデフォルトの割り当てを返す
+    // ファイルに書かれていない操作はデフォルトのまま
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let names: BTreeMap<Action, Vec<String>> = load_ron_or_default(path)?;
+        let mut bindings = Self::default();
+        for (action, names) in names {
+            let keys = names
+                .iter()
+                .filter_map(|name| {
+                    let key = parse_key(name);
+                    if key.is_none() {
+                        warn!("unknown key {} for {:?}", name, action);
+                    }
+                    key
+                })
+                .collect();
+            bindings.keys.insert(action, keys);
+        }
+        Ok(bindings)
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let names: BTreeMap<Action, Vec<String>> = self
+            .keys
+            .iter()
+            .map(|(action, keys)| (*action, keys.iter().map(|key| key_name(*key)).collect()))
+            .collect();
+        save_ron(path, &names)
+    }
+
+    pub fn keys(&self, action: Action) -> &[KeyCode] {
+        self.keys.get(&action).map_or(&[], |keys| keys.as_slice())
+    }
+
+    // actionに割り当てたキーの表示名をseparatorでつなげる
+    pub fn keys_label(&self, action: Action, separator: &str) -> String {
+        self.keys(action)
+            .iter()
+            .map(|key| key_label(*key))
+            .collect::<Vec<_>>()
+            .join(separator)
+    }
+
+    // actionをkeyだけに割り当てる。他の操作に同じキーが割り当てられていれば外す
+    pub fn bind(&mut self, action: Action, key: KeyCode) {
+        for keys in self.keys.values_mut() {
+            keys.retain(|k| *k != key);
+        }
+        self.keys.insert(action, vec![key]);
+    }
+}
+
+#[derive(Resource)]
+pub struct Bindings {
+    // Noneなら保存しない
+    pub path: Option<PathBuf>,
+    pub bindings: KeyBindings,
+}
+
+impl Bindings {
+    pub fn save(&self) {
+        if let Some(path) = &self.path {
+            if let Err(e) = self.bindings.save(path) {
+                warn!("failed to save key bindings {}: {}", path.display(), e);
+            }
+        }
+    }
+}
+
+// そのフレームの操作の状態
+#[derive(Resource, Default)]
+pub struct ActionState {
+    pressed: HashSet<Action>,
+    just_pressed: HashSet<Action>,
+    // 押されてからまだtickで使われていない操作
+    latched: HashSet<Action>,
+}
+
+impl ActionState {
+    pub fn pressed(&self, action: Action) -> bool {
+        self.pressed.contains(&action)
+    }
+
+    pub fn just_pressed(&self, action: Action) -> bool {
+        self.just_pressed.contains(&action)
+    }
+
+    pub fn any_just_pressed(&self) -> bool {
+        !self.just_pressed.is_empty()
+    }
+
+    // キーボード以外の入力から操作を追加する
+    pub fn press(&mut self, action: Action, just_pressed: bool) {
+        self.pressed.insert(action);
+        if just_pressed {
+            self.just_pressed.insert(action);
+            self.latched.insert(action);
+        }
+    }
+
+    // 前に呼んでからactionが押されたか
+    // 描画のフレームとtickがずれていても、短く押したものを取りこぼさないように使う
+    pub fn take_latched(&mut self, action: Action) -> bool {
+        self.latched.remove(&action)
+    }
+
+    pub fn clear_latched(&mut self) {
+        self.latched.clear();
+    }
+}
+
+// ユーザーの設定ディレクトリに置くキー割り当てのパス
+pub fn default_bindings_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("u235").join(BINDINGS_FILE_NAME))
+}
+
+// キー入力を操作に変換する
+// 割り当てはpathの設定ファイルから読み込む
+pub struct ActionPlugin {
+    pub path: Option<PathBuf>,
+}
+
+impl Default for ActionPlugin {
+    fn default() -> Self {
+        Self {
+            path: default_bindings_path(),
+        }
+    }
+}
+
+impl Plugin for ActionPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Bindings {
+            path: self.path.clone(),
+            bindings: KeyBindings::default(),
+        })
+        .init_resource::<ActionState>()
+        .add_systems(Startup, load_bindings)
+        .add_systems(PreUpdate, action_state_system.after(InputSystem));
+    }
+}
+
+fn load_bindings(mut bindings: ResMut<Bindings>) {
+    if let Some(path) = bindings.path.clone() {
+        match KeyBindings::load(&path) {
+            Ok(loaded) => bindings.bindings = loaded,
+            Err(e) => warn!("failed to load key bindings {}: {}", path.display(), e),
+        }
+    }
+}
+
+pub fn action_state_system(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    bindings: Res<Bindings>,
+    mut action_state: ResMut<ActionState>,
+) {
+    action_state.pressed.clear();
+    action_state.just_pressed.clear();
+    for action in Action::all() {
+        let keys = bindings.bindings.keys(action);
+        if keyboard_input.any_pressed(keys.iter().copied()) {
+            action_state.pressed.insert(action);
+        }
+        if keyboard_input.any_just_pressed(keys.iter().copied()) {
+            action_state.just_pressed.insert(action);
+            action_state.latched.insert(action);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    fn load_text(name: &str, text: &str) -> io::Result<KeyBindings> {
+        let path =
+            std::env::temp_dir().join(format!("u235-bindings-{}-{}.ron", name, std::process::id()));
+        fs::write(&path, text).unwrap();
+        let bindings = KeyBindings::load(&path);
+        fs::remove_file(&path).unwrap();
+        bindings
+    }
+
+    #[test]
+    fn missing_file_loads_defaults() {
+        let path = std::env::temp_dir().join("u235-bindings-does-not-exist.ron");
+        assert_eq!(KeyBindings::load(&path).unwrap(), KeyBindings::default());
+    }
+
+    #[test]
+    fn actions_missing_from_file_keep_defaults() {
+        let bindings = load_text("missing", r#"{ Fire: ["KeyZ", "Space"] }"#).unwrap();
+        assert_eq!(bindings.keys(Action::Fire), [KeyCode::KeyZ, KeyCode::Space]);
+        let defaults = KeyBindings::default();
+        for action in Action::all().into_iter().filter(|a| *a != Action::Fire) {
+            assert_eq!(bindings.keys(action), defaults.keys(action));
+        }
+    }
+
+    #[test]
+    fn unknown_keys_are_dropped() {
+        let bindings =
+            load_text("unknown", r#"{ MoveLeft: ["KeyA", "NoSuchKey", "F13"] }"#).unwrap();
+        assert_eq!(bindings.keys(Action::MoveLeft), [KeyCode::KeyA]);
+    }
+
+    #[test]
+    fn unknown_action_is_an_error() {
+        let error = load_text("action", r#"{ Jump: ["KeyA"] }"#).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn saved_bindings_load_back() {
+        let path = std::env::temp_dir().join(format!("u235-bindings-{}.ron", std::process::id()));
+        let mut bindings = KeyBindings::default();
+        bindings.bind(Action::Pause, KeyCode::Enter);
+        bindings.save(&path).unwrap();
+        let loaded = KeyBindings::load(&path);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.unwrap(), bindings);
+    }
+}
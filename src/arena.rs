@@ -0,0 +1,315 @@
+use std::collections::HashSet;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::components::{Position, PLAYER_WIDTH};
+use crate::ron_file::load_ron;
+use crate::CELL_SIZE_PX;
+
+const ARENA_FILE_NAME: &str = "arena.ron";
+// タイトルやHUD、メニューが収まる画面の最小のセル数
+const MIN_SCREEN_COLUMNS: i32 = 40;
+const MIN_SCREEN_ROWS: i32 = 25;
+
+// セルの矩形。min/maxを含む
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CellRect {
+    pub x_min: i32,
+    pub x_max: i32,
+    pub y_min: i32,
+    pub y_max: i32,
+}
+
+impl CellRect {
+    pub fn contains(&self, position: &Position) -> bool {
+        (self.x_min..=self.x_max).contains(&position.x)
+            && (self.y_min..=self.y_max).contains(&position.y)
+    }
+}
+
+// 遊ぶ領域の大きさと壁の配置
+// 領域の左・右・上は壁で囲まれていて、下は開いている
+#[derive(Resource, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(from = "ArenaFile")]
+pub struct Arena {
+    // 壁で囲まれた領域のmin/max
+    pub x_min: i32,
+    pub x_max: i32,
+    pub y_min: i32,
+    pub y_max: i32,
+    // 領域の中に置く壁。弾は跳ね返り、プレイヤーは通れない
+    pub walls: Vec<Position>,
+    // プレイヤーの左端のセルの初期位置
+    pub player_spawn: Position,
+    // ターゲットが出現する範囲
+    pub target_area: CellRect,
+    #[serde(skip)]
+    wall_set: HashSet<Position>,
+}
+
+impl Default for Arena {
+    fn default() -> Self {
+        Self::new(2, 37, 2, 22)
+    }
+}
+
+// ファイルから読むときの形。省略した項目はデフォルトになるが、
+// player_spawnとtarget_areaは省略すると読んだ領域の大きさから決める
+#[derive(Deserialize)]
+#[serde(default)]
+struct ArenaFile {
+    x_min: i32,
+    x_max: i32,
+    y_min: i32,
+    y_max: i32,
+    walls: Vec<Position>,
+    #[serde(deserialize_with = "deserialize_some")]
+    player_spawn: Option<Position>,
+    #[serde(deserialize_with = "deserialize_some")]
+    target_area: Option<CellRect>,
+}
+
+// 書かれていればSome。RONでSome(...)と書かなくてよいようにする
+fn deserialize_some<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    T::deserialize(deserializer).map(Some)
+}
+
+impl Default for ArenaFile {
+    fn default() -> Self {
+        let arena = Arena::default();
+        Self {
+            x_min: arena.x_min,
+            x_max: arena.x_max,
+            y_min: arena.y_min,
+            y_max: arena.y_max,
+            walls: Vec::new(),
+            player_spawn: None,
+            target_area: None,
+        }
+    }
+}
+
+impl From<ArenaFile> for Arena {
+    fn from(file: ArenaFile) -> Self {
+        let mut arena = Arena::new(file.x_min, file.x_max, file.y_min, file.y_max);
+        arena.walls = file.walls;
+        if let Some(player_spawn) = file.player_spawn {
+            arena.player_spawn = player_spawn;
+        }
+        if let Some(target_area) = file.target_area {
+            arena.target_area = target_area;
+        }
+        arena
+    }
+}
+
+impl Arena {
+    // 壁のない領域。プレイヤーは一番下の行の真ん中から始め、
+    // ターゲットは左右の壁際を除いた上の2/3に出る
+    pub fn new(x_min: i32, x_max: i32, y_min: i32, y_max: i32) -> Self {
+        Self {
+            x_min,
+            x_max,
+            y_min,
+            y_max,
+            walls: Vec::new(),
+            player_spawn: Position::new((x_min + x_max) / 2 - PLAYER_WIDTH / 2, y_max),
+            target_area: CellRect {
+                x_min: x_min + 1,
+                x_max: x_max - 1,
+                y_min,
+                y_max: y_min + (y_max - y_min) * 2 / 3,
+            },
+            wall_set: HashSet::new(),
+        }
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let arena = load_ron::<Self>(path)?.with_walls();
+        arena
+            .validate()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        Ok(arena)
+    }
+
+    pub fn with_walls(mut self) -> Self {
+        self.update_walls();
+        self
+    }
+
+    // wallsを変えたら呼ぶ
+    pub fn update_walls(&mut self) {
+        self.wall_set = self.walls.iter().cloned().collect();
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        // 左・上の壁とその外側のタイトル・HUDの行が画面に入るようにする
+        if self.x_min < 2 || self.y_min < 2 {
+            return Err("x_min and y_min must be at least 2".into());
+        }
+        if self.x_max - self.x_min + 1 < PLAYER_WIDTH + 2 || self.y_max <= self.y_min {
+            return Err("arena is too small".into());
+        }
+        let area = self.area();
+        if !(0..PLAYER_WIDTH).all(|dx| self.is_open(&self.player_spawn.add(dx, 0))) {
+            return Err("player_spawn must be inside the arena and not on a wall".into());
+        }
+        if self.target_area.x_min > self.target_area.x_max
+            || self.target_area.y_min > self.target_area.y_max
+            || !area.contains(&Position::new(
+                self.target_area.x_min,
+                self.target_area.y_min,
+            ))
+            || !area.contains(&Position::new(
+                self.target_area.x_max,
+                self.target_area.y_max,
+            ))
+        {
+            return Err("target_area must be inside the arena".into());
+        }
+        if let Some(wall) = self.walls.iter().find(|wall| !area.contains(wall)) {
+            return Err(format!(
+                "wall ({}, {}) is outside the arena",
+                wall.x, wall.y
+            ));
+        }
+        Ok(())
+    }
+
+    // 壁で囲まれた領域
+    pub fn area(&self) -> CellRect {
+        CellRect {
+            x_min: self.x_min,
+            x_max: self.x_max,
+            y_min: self.y_min,
+            y_max: self.y_max,
+        }
+    }
+
+    // 左・右・上の壁と置かれた壁。下は開いているので壁ではない
+    pub fn is_wall(&self, position: &Position) -> bool {
+        position.x < self.x_min
+            || position.x > self.x_max
+            || position.y < self.y_min
+            || self.is_placed_wall(position)
+    }
+
+    pub fn is_placed_wall(&self, position: &Position) -> bool {
+        self.wall_set.contains(position)
+    }
+
+    // 壁で囲まれた領域の中で、置かれた壁でないセル
+    pub fn is_open(&self, position: &Position) -> bool {
+        self.area().contains(position) && !self.is_placed_wall(position)
+    }
+}
+
+// 画面のセル数
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Screen {
+    pub columns: i32,
+    pub rows: i32,
+}
+
+impl Screen {
+    // どの領域も、その外側の壁と下の武器の表示の行まで収まる大きさ
+    pub fn fit<'a>(arenas: impl IntoIterator<Item = &'a Arena>) -> Self {
+        let mut screen = Self {
+            columns: MIN_SCREEN_COLUMNS,
+            rows: MIN_SCREEN_ROWS,
+        };
+        for arena in arenas {
+            screen.columns = screen.columns.max(arena.x_max + 3);
+            screen.rows = screen.rows.max(arena.y_max + 3);
+        }
+        screen
+    }
+
+    // 領域がその外側の壁と下の武器の表示の行まで収まるか
+    pub fn fits(&self, arena: &Arena) -> bool {
+        arena.x_max + 3 <= self.columns && arena.y_max + 3 <= self.rows
+    }
+
+    pub fn size(&self) -> Vec2 {
+        Vec2::new(
+            CELL_SIZE_PX * self.columns as f32,
+            CELL_SIZE_PX * self.rows as f32,
+        )
+    }
+}
+
+// ユーザーの設定ディレクトリに置く領域の設定のパス
+pub fn default_arena_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("u235").join(ARENA_FILE_NAME))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Arena {
+        ron::from_str::<Arena>(text).unwrap().with_walls()
+    }
+
+    #[test]
+    fn default_arena_is_valid() {
+        assert_eq!(Arena::default().validate(), Ok(()));
+    }
+
+    #[test]
+    fn rejects_arena_off_screen_or_too_small() {
+        assert!(Arena::new(1, 37, 2, 22).validate().is_err());
+        assert!(Arena::new(2, 37, 1, 22).validate().is_err());
+        assert!(Arena::new(2, 2 + PLAYER_WIDTH, 2, 22).validate().is_err());
+        assert_eq!(Arena::new(2, 3 + PLAYER_WIDTH, 2, 22).validate(), Ok(()));
+        assert!(Arena::new(2, 37, 2, 2).validate().is_err());
+    }
+
+    #[test]
+    fn rejects_player_spawn_outside_or_on_wall() {
+        let mut arena = Arena::default();
+        arena.player_spawn = Position::new(arena.x_max - PLAYER_WIDTH + 2, arena.y_max);
+        assert!(arena.validate().is_err());
+
+        let mut arena = Arena::default();
+        arena.walls = vec![arena.player_spawn.add(PLAYER_WIDTH - 1, 0)];
+        let arena = arena.with_walls();
+        assert!(arena.validate().is_err());
+    }
+
+    #[test]
+    fn rejects_target_area_outside_or_empty() {
+        let mut arena = Arena::default();
+        arena.target_area.y_min = arena.y_min - 1;
+        assert!(arena.validate().is_err());
+
+        let mut arena = Arena::default();
+        arena.target_area.x_max = arena.target_area.x_min - 1;
+        assert!(arena.validate().is_err());
+    }
+
+    #[test]
+    fn rejects_wall_outside() {
+        let mut arena = Arena::default();
+        arena.walls = vec![Position::new(arena.x_max + 1, arena.y_min)];
+        assert!(arena.with_walls().validate().is_err());
+    }
+
+    #[test]
+    fn partial_file_derives_spawn_and_target_area() {
+        let arena = parse("(x_max: 20, y_max: 12)");
+        assert_eq!(arena, Arena::new(2, 20, 2, 12));
+        assert_eq!(arena.validate(), Ok(()));
+
+        let arena = parse("(x_max: 20, y_max: 12, player_spawn: (x: 3, y: 12))");
+        assert_eq!(arena.player_spawn, Position::new(3, 12));
+        assert_eq!(arena.target_area, Arena::new(2, 20, 2, 12).target_area);
+    }
+}
@@ -2,7 +2,15 @@ use bevy::prelude::*;
 
 pub const IMAGE_BACK: &str = "images/back.png";
 pub const IMAGE_DOWN: &str = "images/down.png";
+pub const IMAGE_DOWN_LEFT: &str = "images/down_left.png";
+pub const IMAGE_DOWN_RIGHT: &str = "images/down_right.png";
 pub const IMAGE_DUST: &str = "images/dust.png";
+pub const IMAGE_FONT: &str = "images/font.png";
+pub const IMAGE_FONT_TILE_SIZE: Vec2 = Vec2::new(8.0, 16.0);
+pub const IMAGE_FONT_TILE_COLUMNS: usize = 16;
+pub const IMAGE_FONT_TILE_ROWS: usize = 4;
+// font.pngにはこの文字から'_'までのASCII文字が順に並んでいる
+pub const FONT_FIRST_CHAR: char = ' ';
 pub const IMAGE_LEFT: &str = "images/left.png";
 pub const IMAGE_NUMBERS: &str = "images/numbers.png";
 pub const IMAGE_NUMBERS_TILE_SIZE: Vec2 = Vec2::new(8.0, 16.0);
@@ -13,6 +21,8 @@ pub const IMAGE_RIGHT: &str = "images/right.png";
 pub const IMAGE_TARGET: &str = "images/target.png";
 pub const IMAGE_TITLE: &str = "images/title.png";
 pub const IMAGE_UP: &str = "images/up.png";
+pub const IMAGE_UP_LEFT: &str = "images/up_left.png";
+pub const IMAGE_UP_RIGHT: &str = "images/up_right.png";
 pub const IMAGE_WALL: &str = "images/wall.png";
 pub const SOUND_CRASH: &str = "sounds/crash.wav";
 pub const SOUND_HIT: &str = "sounds/hit.wav";
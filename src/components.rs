@@ -1,11 +1,12 @@
 use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
 
 // このmarkerをつけたComponentはリスタート時にdespawnされる
 // https://www.reddit.com/r/bevy/comments/17er37y/comment/k65wjdn/?utm_source=share&utm_medium=web3x&utm_name=web3xcss&utm_term=1&utm_content=share_button
 #[derive(Component)]
 pub struct DespawnOnRestart;
 
-#[derive(Debug, Default, Clone, Eq, PartialEq, Component)]
+#[derive(Debug, Default, Clone, Eq, PartialEq, Hash, Component, Serialize, Deserialize)]
 pub struct Position {
     pub x: i32,
     pub y: i32,
@@ -24,16 +25,38 @@ impl Position {
     }
 }
 
-#[derive(Debug, Clone, Eq, PartialEq, Component)]
+// tickの初めの位置。衝突判定でそのtickの移動経路を見るのに使う
+#[derive(Debug, Default, Clone, Eq, PartialEq, Component)]
+pub struct PreviousPosition(pub Position);
+
+#[derive(Debug, Clone, Eq, PartialEq, Component, Serialize, Deserialize)]
 pub enum Direction {
     Up,
     Left,
     Down,
     Right,
+    UpLeft,
+    UpRight,
+    DownLeft,
+    DownRight,
 }
 
 impl Direction {
-    pub fn all() -> [Self; 4] {
+    pub fn all() -> [Self; 8] {
+        [
+            Direction::Up,
+            Direction::Left,
+            Direction::Down,
+            Direction::Right,
+            Direction::UpLeft,
+            Direction::UpRight,
+            Direction::DownLeft,
+            Direction::DownRight,
+        ]
+    }
+
+    // 上下左右
+    pub fn cardinal() -> [Self; 4] {
         [
             Direction::Up,
             Direction::Left,
@@ -49,6 +72,10 @@ impl Direction {
             1 => Direction::Left,
             2 => Direction::Down,
             3 => Direction::Right,
+            4 => Direction::UpLeft,
+            5 => Direction::UpRight,
+            6 => Direction::DownLeft,
+            7 => Direction::DownRight,
             _ => panic!(),
         }
     }
@@ -59,36 +86,306 @@ impl Direction {
             Direction::Left => 1,
             Direction::Down => 2,
             Direction::Right => 3,
+            Direction::UpLeft => 4,
+            Direction::UpRight => 5,
+            Direction::DownLeft => 6,
+            Direction::DownRight => 7,
         }
     }
 
-    pub fn opposite(&self) -> Self {
+    // 1tickに動くセル数
+    pub fn delta(&self) -> (i32, i32) {
         match self {
-            Direction::Up => Direction::Down,
-            Direction::Left => Direction::Right,
-            Direction::Down => Direction::Up,
-            Direction::Right => Direction::Left,
+            Direction::Up => (0, -1),
+            Direction::Left => (-1, 0),
+            Direction::Down => (0, 1),
+            Direction::Right => (1, 0),
+            Direction::UpLeft => (-1, -1),
+            Direction::UpRight => (1, -1),
+            Direction::DownLeft => (-1, 1),
+            Direction::DownRight => (1, 1),
         }
     }
 
+    pub fn from_delta(dx: i32, dy: i32) -> Option<Self> {
+        Self::all()
+            .into_iter()
+            .find(|direction| direction.delta() == (dx.signum(), dy.signum()))
+    }
+
+    pub fn is_diagonal(&self) -> bool {
+        let (dx, dy) = self.delta();
+        dx != 0 && dy != 0
+    }
+
+    pub fn opposite(&self) -> Self {
+        self.reflect(true, true)
+    }
+
+    // 横・縦の向きを反転する
+    pub fn reflect(&self, flip_x: bool, flip_y: bool) -> Self {
+        let (dx, dy) = self.delta();
+        let dx = if flip_x { -dx } else { dx };
+        let dy = if flip_y { -dy } else { dy };
+        Self::from_delta(dx, dy).unwrap()
+    }
+
     pub fn neighbor(&self, pos: Position) -> Position {
-        match self {
-            Direction::Up => pos.add(0, -1),
-            Direction::Left => pos.add(-1, 0),
-            Direction::Down => pos.add(0, 1),
-            Direction::Right => pos.add(1, 0),
-        }
+        let (dx, dy) = self.delta();
+        pos.add(dx, dy)
     }
 }
 
 #[derive(Component)]
 pub struct Player;
 
+// プレイヤーのセル幅。Positionは左端のセル
+pub const PLAYER_WIDTH: i32 = 3;
+
+// 出し直したプレイヤーが弾に当たらない残りtick数
+#[derive(Component)]
+pub struct Invulnerable {
+    pub ticks: u32,
+}
+
+// プレイヤーがやられてから出し直すまでの残りtick数
+#[derive(Component)]
+pub struct Respawning {
+    pub ticks: u32,
+}
+
 #[derive(Component)]
 pub struct Bullet;
 
+// 破裂の弾が何段続いても、スコアの倍率はこれ以上にならない
+const MAX_CHAIN_MULTIPLIER: u32 = 8;
+
+// 弾の系譜。どのプレイヤーの射撃から始まった連鎖の、何段目の弾か
+// プレイヤーの弾は0段目で、その弾で壊したターゲットの破裂の弾は1段目
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Component)]
+pub struct Chain {
+    pub id: u32,
+    pub depth: u32,
+}
+
+impl Chain {
+    // この弾で壊したターゲットから出る弾
+    pub fn next(&self) -> Self {
+        Self {
+            id: self.id,
+            depth: self.depth + 1,
+        }
+    }
+
+    // この弾でターゲットを壊したときのスコアの倍率
+    pub fn multiplier(&self) -> i32 {
+        (self.depth + 1).min(MAX_CHAIN_MULTIPLIER) as i32
+    }
+}
+
+// プレイヤーが撃った弾。画面上の数を数えるのに使う
+#[derive(Component)]
+pub struct PlayerShot;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum WeaponKind {
+    Single,
+    Twin,
+    Spread,
+}
+
+impl WeaponKind {
+    pub fn all() -> [Self; 3] {
+        [WeaponKind::Single, WeaponKind::Twin, WeaponKind::Spread]
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            WeaponKind::Single => "SINGLE",
+            WeaponKind::Twin => "TWIN",
+            WeaponKind::Spread => "SPREAD",
+        }
+    }
+
+    // このスコアに達したら選べるようになる
+    pub fn unlock_score(&self) -> i32 {
+        match self {
+            WeaponKind::Single => 0,
+            WeaponKind::Twin => 10000,
+            WeaponKind::Spread => 30000,
+        }
+    }
+
+    // 1回に撃つ弾の、プレイヤーの左上から見た位置と向き
+    // spreadは斜めにも撃って扇形に広がる
+    pub fn pattern(&self) -> &'static [(i32, i32, Direction)] {
+        match self {
+            WeaponKind::Single => &[(1, -1, Direction::Up)],
+            WeaponKind::Twin => &[(0, -1, Direction::Up), (2, -1, Direction::Up)],
+            WeaponKind::Spread => &[
+                (0, -1, Direction::UpLeft),
+                (1, -1, Direction::Up),
+                (2, -1, Direction::UpRight),
+            ],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Component)]
+pub struct Weapon {
+    pub kind: WeaponKind,
+    // 次に撃てるまでのtick数
+    pub cooldown: u32,
+    // 1回撃つと続けて撃つ回数と、その間隔(tick)
+    pub burst: u32,
+    pub burst_interval: u32,
+    // 画面上に出せるプレイヤーの弾の数
+    pub max_bullets: usize,
+    // 次に撃てるまでの残りtick数
+    pub timer: u32,
+    // バーストの残り回数
+    pub burst_left: u32,
+}
+
+impl Weapon {
+    pub fn new(kind: WeaponKind) -> Self {
+        let (cooldown, burst, burst_interval, max_bullets) = match kind {
+            WeaponKind::Single => (1, 1, 1, 64),
+            WeaponKind::Twin => (2, 1, 1, 48),
+            WeaponKind::Spread => (5, 2, 1, 60),
+        };
+        Self {
+            kind,
+            cooldown,
+            burst,
+            burst_interval,
+            max_bullets,
+            timer: 0,
+            burst_left: 0,
+        }
+    }
+}
+
+// 破裂時に下向きに飛ぶ弾はdustのスプライトで描画する
 #[derive(Component)]
-pub struct Target;
+pub struct Dust;
+
+fn default_shot_distance() -> u32 {
+    1
+}
+
+// 破裂で出す1つの弾
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Shot {
+    pub direction: Direction,
+    // ターゲットから何セル離れたところに出すか
+    #[serde(default = "default_shot_distance")]
+    pub distance: u32,
+    // dustのスプライトで描画する
+    #[serde(default)]
+    pub dust: bool,
+}
+
+// 同時に出す弾の組
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Burst {
+    // 壊れてから出すまでのtick数
+    #[serde(default)]
+    pub delay: u32,
+    pub shots: Vec<Shot>,
+}
+
+// ターゲットが最後に壊れたときの破裂の仕方
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Explosion {
+    // 上下左右に弾を出す。下向きの弾はdust
+    #[default]
+    Cross,
+    // 弾を出さずに消える
+    Vanish,
+    // 面のファイルで決めた弾の組を順に出す
+    Bursts(Vec<Burst>),
+}
+
+impl Explosion {
+    pub fn bursts(&self) -> Vec<Burst> {
+        match self {
+            Explosion::Cross => vec![Burst {
+                delay: 0,
+                shots: Direction::cardinal()
+                    .into_iter()
+                    .map(|direction| Shot {
+                        dust: direction == Direction::Down,
+                        direction,
+                        distance: 1,
+                    })
+                    .collect(),
+            }],
+            Explosion::Vanish => Vec::new(),
+            Explosion::Bursts(bursts) => bursts.clone(),
+        }
+    }
+}
 
+// 時間差で出す弾。game.ticksがtickになったら出して消える
 #[derive(Component)]
-pub struct NumberType(pub &'static str, pub usize);
+pub struct PendingBurst {
+    pub tick: u32,
+    pub shots: Vec<Shot>,
+    pub chain: Chain,
+}
+
+// ターゲットの種類。面のファイルで定義する
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TargetKind {
+    // 壊れるまでに当てる弾の数
+    pub hp: u32,
+    // 壊したときのスコア
+    pub score: i32,
+    // 残りHPごとのスプライトの色(RGB)。1つめが残りHP1のとき
+    // 足りなければ最後の色を使う。空なら白
+    pub colors: Vec<[f32; 3]>,
+    pub explosion: Explosion,
+}
+
+impl Default for TargetKind {
+    fn default() -> Self {
+        Self {
+            hp: 1,
+            score: 1000,
+            colors: Vec::new(),
+            explosion: Explosion::Cross,
+        }
+    }
+}
+
+impl TargetKind {
+    pub fn color(&self, hp: u32) -> Color {
+        let index = (hp.max(1) - 1) as usize;
+        match self.colors.get(index).or(self.colors.last()) {
+            Some([r, g, b]) => Color::rgb(*r, *g, *b),
+            None => Color::WHITE,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Component)]
+pub struct Target {
+    pub kind: TargetKind,
+    // 残りHP
+    pub hp: u32,
+}
+
+impl Target {
+    pub fn new(kind: TargetKind) -> Self {
+        Self {
+            hp: kind.hp.max(1),
+            kind,
+        }
+    }
+
+    pub fn color(&self) -> Color {
+        self.kind.color(self.hp)
+    }
+}
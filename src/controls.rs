@@ -0,0 +1,149 @@
+use bevy::prelude::*;
+
+use crate::actions::*;
+use crate::arena::*;
+use crate::hud::{spawn_centered_text, spawn_text};
+use crate::states::*;
+
+const TITLE_TEXT: &str = "CONTROLS";
+const WAITING_TEXT: &str = "PRESS A KEY";
+const HELP: &str = "UP DOWN - SELECT   ENTER - CHANGE";
+const HELP2: &str = "DELETE - DEFAULTS   ESC - BACK";
+const TOP: i32 = 4;
+// 操作名とキーを置くセルのx座標
+const LABEL_X: i32 = 8;
+const KEYS_X: i32 = 16;
+
+// 選んでいる操作と、キー入力を待っているか
+#[derive(Resource, Default)]
+struct ControlsMenu {
+    selected: usize,
+    waiting: bool,
+}
+
+#[derive(Component)]
+struct ControlsScreen;
+
+// 操作設定画面
+// 割り当てを変えられなくならないように、この画面の操作はキー割り当てによらず固定にする
+pub struct ControlsPlugin;
+
+impl Plugin for ControlsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ControlsMenu>()
+            .add_systems(OnEnter(GameState::Controls), controls_enter)
+            .add_systems(OnExit(GameState::Controls), controls_exit)
+            .add_systems(
+                Update,
+                (
+                    controls_system,
+                    controls_screen_system.run_if(
+                        resource_changed::<ControlsMenu>.or_else(resource_changed::<Bindings>),
+                    ),
+                )
+                    .chain()
+                    .run_if(in_state(GameState::Controls)),
+            );
+    }
+}
+
+fn controls_enter(mut menu: ResMut<ControlsMenu>) {
+    *menu = ControlsMenu::default();
+}
+
+fn controls_exit(
+    mut commands: Commands,
+    bindings: Res<Bindings>,
+    query: Query<Entity, With<ControlsScreen>>,
+) {
+    for entity in &query {
+        commands.entity(entity).despawn_recursive();
+    }
+    bindings.save();
+}
+
+fn controls_system(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    mut menu: ResMut<ControlsMenu>,
+    mut bindings: ResMut<Bindings>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    let actions = Action::all();
+    if menu.waiting {
+        let Some(key) = keyboard_input.get_just_pressed().next().copied() else {
+            return;
+        };
+        if key == KeyCode::Escape {
+            menu.waiting = false;
+        } else if is_bindable(key) {
+            bindings.bindings.bind(actions[menu.selected], key);
+            menu.waiting = false;
+        }
+        return;
+    }
+
+    if keyboard_input.just_pressed(KeyCode::ArrowUp) {
+        menu.selected = (menu.selected + actions.len() - 1) % actions.len();
+    }
+    if keyboard_input.just_pressed(KeyCode::ArrowDown) {
+        menu.selected = (menu.selected + 1) % actions.len();
+    }
+    if keyboard_input.just_pressed(KeyCode::Enter) {
+        menu.waiting = true;
+    }
+    if keyboard_input.just_pressed(KeyCode::Delete) {
+        bindings.bindings = KeyBindings::default();
+    }
+    if keyboard_input.just_pressed(KeyCode::Escape) {
+        next_state.set(GameState::Title);
+    }
+}
+
+// 変更があるたびに画面を作り直す
+fn controls_screen_system(
+    mut commands: Commands,
+    menu: Res<ControlsMenu>,
+    bindings: Res<Bindings>,
+    screen: Res<Screen>,
+    query: Query<Entity, With<ControlsScreen>>,
+) {
+    for entity in &query {
+        commands.entity(entity).despawn_recursive();
+    }
+
+    let mut entities = vec![spawn_centered_text(
+        TITLE_TEXT,
+        TOP,
+        Color::YELLOW,
+        &screen,
+        &mut commands,
+    )];
+    for (i, action) in Action::all().iter().enumerate() {
+        let y = TOP + 3 + i as i32 * 2;
+        let color = if i == menu.selected {
+            Color::YELLOW
+        } else {
+            Color::WHITE
+        };
+        let keys = if i == menu.selected && menu.waiting {
+            WAITING_TEXT.to_string()
+        } else {
+            bindings.bindings.keys_label(*action, "  ")
+        };
+        entities.push(spawn_text(action.label(), LABEL_X, y, color, &mut commands));
+        entities.push(spawn_text(&keys, KEYS_X, y, color, &mut commands));
+    }
+    for (help, y) in [(HELP, TOP + 16), (HELP2, TOP + 17)] {
+        entities.push(spawn_centered_text(
+            help,
+            y,
+            Color::WHITE,
+            &screen,
+            &mut commands,
+        ));
+    }
+
+    for entity in entities {
+        commands.entity(entity).insert(ControlsScreen);
+    }
+}
@@ -0,0 +1,511 @@
+use std::io;
+use std::path::PathBuf;
+
+use bevy::prelude::*;
+
+use crate::arena::*;
+use crate::components::*;
+use crate::graphics::{create_top_left_sprite, position_to_transform, spawn_arena};
+use crate::hud::spawn_text;
+use crate::level::*;
+use crate::resources::*;
+use crate::states::*;
+use crate::CELL_SIZE_PX;
+
+const EDITOR_FILE_NAME: &str = "editor.ron";
+const HELP: &str = "TAB TOOL K KIND SPACE PUT DEL CLEAR SHIFT SIZE S SAVE L LOAD F5 TEST ESC BACK";
+// 試しに遊び始める・エディタに戻るキー
+const PLAYTEST_KEY: KeyCode = KeyCode::F5;
+
+// カーソルで置くもの
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tool {
+    Wall,
+    Target,
+    Player,
+    // ターゲットがランダムに出現する範囲。2つの角を順に置く
+    TargetArea,
+}
+
+impl Tool {
+    fn next(self) -> Self {
+        match self {
+            Tool::Wall => Tool::Target,
+            Tool::Target => Tool::Player,
+            Tool::Player => Tool::TargetArea,
+            Tool::TargetArea => Tool::Wall,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Tool::Wall => "WALL",
+            Tool::Target => "TARGET",
+            Tool::Player => "PLAYER",
+            Tool::TargetArea => "TARGET AREA",
+        }
+    }
+}
+
+// 編集中の面
+#[derive(Resource)]
+pub struct Editor {
+    // Noneなら保存しない
+    pub path: Option<PathBuf>,
+    pub level: Level,
+    pub cursor: Position,
+    pub tool: Tool,
+    // 置くターゲットの種類
+    pub target_kind: String,
+    // 置いたTargetAreaの1つめの角
+    area_corner: Option<Position>,
+    // 保存・読み込みの結果
+    message: &'static str,
+    // 試しに遊んでいる間、元の面をとっておく
+    saved_levels: Option<Levels>,
+}
+
+impl Editor {
+    fn new(path: Option<PathBuf>) -> Self {
+        let level = Level {
+            spawn: SpawnRule {
+                chance: 0.0,
+                max_targets: 0,
+                kinds: Vec::new(),
+            },
+            win: WinCondition::ClearTargets,
+            ..default()
+        };
+        Self {
+            path,
+            cursor: level.arena.player_spawn.clone(),
+            level,
+            tool: Tool::Wall,
+            target_kind: DEFAULT_TARGET_KIND.into(),
+            area_corner: None,
+            message: "",
+            saved_levels: None,
+        }
+    }
+
+    fn put(&mut self) {
+        let cursor = self.cursor.clone();
+        let level = &mut self.level;
+        match self.tool {
+            Tool::Wall => {
+                if !level.arena.is_placed_wall(&cursor) && !player_cells(level).contains(&cursor) {
+                    level.targets.retain(|t| t.position() != cursor);
+                    level.arena.walls.push(cursor);
+                    level.arena.update_walls();
+                }
+            }
+            Tool::Target => {
+                // 置いてあるターゲットは種類を置き換える
+                if level.arena.is_open(&cursor) {
+                    level.targets.retain(|t| t.position() != cursor);
+                    level
+                        .targets
+                        .push(PlacedTarget::new(cursor, &self.target_kind));
+                }
+            }
+            Tool::Player => {
+                if (0..PLAYER_WIDTH).all(|dx| level.arena.is_open(&cursor.add(dx, 0))) {
+                    level.arena.player_spawn = cursor;
+                }
+            }
+            Tool::TargetArea => match self.area_corner.take() {
+                None => self.area_corner = Some(cursor),
+                Some(corner) => {
+                    level.arena.target_area = CellRect {
+                        x_min: corner.x.min(cursor.x),
+                        x_max: corner.x.max(cursor.x),
+                        y_min: corner.y.min(cursor.y),
+                        y_max: corner.y.max(cursor.y),
+                    };
+                }
+            },
+        }
+    }
+
+    fn clear(&mut self) {
+        let cursor = self.cursor.clone();
+        let level = &mut self.level;
+        level.targets.retain(|t| t.position() != cursor);
+        level.arena.walls.retain(|w| *w != cursor);
+        level.arena.update_walls();
+    }
+
+    // 領域の右下を動かす。画面からはみ出さず、プレイヤーが動ける幅は残す
+    // 画面が狭くて両方を満たせなければ、プレイヤーが動ける幅を優先する
+    fn resize(&mut self, dx: i32, dy: i32, screen: &Screen) {
+        let arena = &mut self.level.arena;
+        let x_max_min = arena.x_min + PLAYER_WIDTH + 1;
+        let y_max_min = arena.y_min + 1;
+        arena.x_max = (arena.x_max + dx).clamp(x_max_min, (screen.columns - 3).max(x_max_min));
+        arena.y_max = (arena.y_max + dy).clamp(y_max_min, (screen.rows - 3).max(y_max_min));
+        let area = arena.area();
+        arena.walls.retain(|w| area.contains(w));
+        arena.update_walls();
+        arena.player_spawn.x = arena.player_spawn.x.min(arena.x_max - PLAYER_WIDTH + 1);
+        arena.player_spawn.y = arena.player_spawn.y.min(arena.y_max);
+        let target_area = &mut arena.target_area;
+        target_area.x_max = target_area.x_max.min(area.x_max);
+        target_area.y_max = target_area.y_max.min(area.y_max);
+        target_area.x_min = target_area.x_min.min(target_area.x_max);
+        target_area.y_min = target_area.y_min.min(target_area.y_max);
+        self.level.targets.retain(|t| area.contains(&t.position()));
+        self.move_cursor(0, 0);
+    }
+
+    // 面で定義されている次の種類にする
+    fn next_target_kind(&mut self) {
+        let names = self.level.target_kind_names();
+        let index = names.iter().position(|name| *name == self.target_kind);
+        self.target_kind = names[index.map_or(0, |i| (i + 1) % names.len())].clone();
+    }
+
+    fn move_cursor(&mut self, dx: i32, dy: i32) {
+        let arena = &self.level.arena;
+        self.cursor = Position::new(
+            (self.cursor.x + dx).clamp(arena.x_min, arena.x_max),
+            (self.cursor.y + dy).clamp(arena.y_min, arena.y_max),
+        );
+    }
+
+    fn save(&mut self) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        if let Err(e) = self.level.validate() {
+            warn!("invalid level: {}", e);
+            self.message = "INVALID LEVEL";
+            return;
+        }
+        self.message = match self.level.save(path) {
+            Ok(()) => "SAVED",
+            Err(e) => {
+                warn!("failed to save level {}: {}", path.display(), e);
+                "SAVE FAILED"
+            }
+        };
+    }
+
+    // 画面に収まらない面は読まない
+    fn load(&mut self, screen: &Screen) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        self.message = match Level::load(path) {
+            Ok(level) if !screen.fits(&level.arena) => {
+                warn!(
+                    "level {} does not fit in the {}x{} screen",
+                    path.display(),
+                    screen.columns,
+                    screen.rows
+                );
+                "TOO LARGE"
+            }
+            Ok(level) => {
+                self.level = level;
+                self.area_corner = None;
+                if self.level.target_kind(&self.target_kind).is_none() {
+                    self.target_kind = DEFAULT_TARGET_KIND.into();
+                }
+                self.move_cursor(0, 0);
+                "LOADED"
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => "NO FILE",
+            Err(e) => {
+                warn!("failed to load level {}: {}", path.display(), e);
+                "LOAD FAILED"
+            }
+        };
+    }
+}
+
+// プレイヤーが最初にいるセル
+fn player_cells(level: &Level) -> Vec<Position> {
+    (0..PLAYER_WIDTH)
+        .map(|dx| level.arena.player_spawn.add(dx, 0))
+        .collect()
+}
+
+// ユーザーのデータディレクトリに置く、編集中の面のパス
+pub fn default_editor_path() -> Option<PathBuf> {
+    dirs::data_dir().map(|dir| dir.join("u235").join(EDITOR_FILE_NAME))
+}
+
+#[derive(Component)]
+struct EditorScreen;
+
+// 面のエディタ
+// カーソルで壁・ターゲット・プレイヤーの位置・ターゲットの出現範囲を置き、pathの面のファイルに保存する
+// F5で作っている面を試しに遊び、もう一度F5を押すとエディタに戻る
+// 操作設定画面と同じく、キー割り当てによらずキーは固定
+pub struct EditorPlugin {
+    pub path: Option<PathBuf>,
+}
+
+impl Default for EditorPlugin {
+    fn default() -> Self {
+        Self {
+            path: default_editor_path(),
+        }
+    }
+}
+
+impl Plugin for EditorPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Editor::new(self.path.clone()))
+            .add_systems(Startup, load_editor_level)
+            .add_systems(OnEnter(GameState::Editor), (end_playtest, editor_enter))
+            .add_systems(OnExit(GameState::Editor), editor_exit)
+            .add_systems(OnEnter(GameState::Title), end_playtest)
+            .add_systems(
+                Update,
+                (
+                    editor_system,
+                    editor_screen_system.run_if(resource_changed::<Editor>),
+                )
+                    .chain()
+                    .run_if(in_state(GameState::Editor)),
+            )
+            .add_systems(
+                Update,
+                playtest_exit_system.run_if(|playtesting: Res<Playtesting>| playtesting.0),
+            );
+    }
+}
+
+fn load_editor_level(mut editor: ResMut<Editor>, screen: Res<Screen>) {
+    editor.load(&screen);
+    editor.message = "";
+}
+
+// ゲームの残りを消して、画面を作り直す
+fn editor_enter(
+    mut commands: Commands,
+    mut editor: ResMut<Editor>,
+    query: Query<Entity, With<DespawnOnRestart>>,
+) {
+    for entity in &query {
+        commands.entity(entity).despawn_recursive();
+    }
+    editor.message = "";
+}
+
+fn editor_exit(mut commands: Commands, query: Query<Entity, With<EditorScreen>>) {
+    for entity in &query {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+fn editor_system(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    mut editor: ResMut<Editor>,
+    screen: Res<Screen>,
+    mut levels: ResMut<Levels>,
+    mut playtesting: ResMut<Playtesting>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    let shift = keyboard_input.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+    for (key, dx, dy) in [
+        (KeyCode::ArrowLeft, -1, 0),
+        (KeyCode::ArrowRight, 1, 0),
+        (KeyCode::ArrowUp, 0, -1),
+        (KeyCode::ArrowDown, 0, 1),
+    ] {
+        if keyboard_input.just_pressed(key) {
+            if shift {
+                editor.resize(dx, dy, &screen);
+            } else {
+                editor.move_cursor(dx, dy);
+            }
+        }
+    }
+    if keyboard_input.just_pressed(KeyCode::Tab) {
+        editor.tool = editor.tool.next();
+        editor.area_corner = None;
+    }
+    if keyboard_input.just_pressed(KeyCode::KeyK) {
+        editor.next_target_kind();
+    }
+    if keyboard_input.any_just_pressed([KeyCode::Space, KeyCode::Enter]) {
+        editor.put();
+    }
+    if keyboard_input.any_just_pressed([KeyCode::Delete, KeyCode::Backspace]) {
+        editor.clear();
+    }
+    if keyboard_input.just_pressed(KeyCode::KeyS) {
+        editor.save();
+    }
+    if keyboard_input.just_pressed(KeyCode::KeyL) {
+        editor.load(&screen);
+    }
+    if keyboard_input.just_pressed(PLAYTEST_KEY) {
+        if let Err(e) = editor.level.validate() {
+            warn!("invalid level: {}", e);
+            editor.message = "INVALID LEVEL";
+        } else {
+            // 作っている面だけを遊ぶ
+            let level = editor.level.clone();
+            editor.saved_levels = Some(std::mem::replace(
+                &mut *levels,
+                Levels {
+                    levels: vec![level],
+                },
+            ));
+            playtesting.0 = true;
+            next_state.set(GameState::Playing);
+        }
+    }
+    if keyboard_input.just_pressed(KeyCode::Escape) {
+        next_state.set(GameState::Title);
+    }
+}
+
+// 試しに遊んでいる間にF5を押したらエディタに戻る
+fn playtest_exit_system(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    state: Res<State<GameState>>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if *state.get() != GameState::Editor && keyboard_input.just_pressed(PLAYTEST_KEY) {
+        next_state.set(GameState::Editor);
+    }
+}
+
+// 元の面に戻す
+fn end_playtest(
+    mut editor: ResMut<Editor>,
+    mut levels: ResMut<Levels>,
+    mut playtesting: ResMut<Playtesting>,
+) {
+    if let Some(saved_levels) = editor.saved_levels.take() {
+        *levels = saved_levels;
+    }
+    playtesting.0 = false;
+}
+
+// 変更があるたびに画面を作り直す
+fn editor_screen_system(
+    mut commands: Commands,
+    editor: Res<Editor>,
+    textures: Res<Textures>,
+    query: Query<Entity, With<EditorScreen>>,
+) {
+    for entity in &query {
+        commands.entity(entity).despawn_recursive();
+    }
+
+    let level = &editor.level;
+    let arena = &level.arena;
+    let mut entities = spawn_arena(arena, &mut commands, &textures);
+
+    // ターゲットの出現範囲
+    let area = &arena.target_area;
+    let mut area_pos = position_to_transform(Position::new(area.x_min, area.y_min));
+    area_pos.translation.z = 1.0;
+    entities.push(spawn_rect(
+        area_pos,
+        area.x_max - area.x_min + 1,
+        area.y_max - area.y_min + 1,
+        Color::rgba(0.2, 0.4, 1.0, 0.25),
+        &mut commands,
+    ));
+
+    for target in &level.targets {
+        let kind = level.target_kind(&target.kind).unwrap_or_default();
+        entities.push(
+            commands
+                .spawn(SpriteBundle {
+                    texture: textures.target.clone(),
+                    transform: position_to_transform(target.position()),
+                    sprite: Sprite {
+                        color: kind.color(kind.hp),
+                        ..create_top_left_sprite()
+                    },
+                    ..default()
+                })
+                .id(),
+        );
+    }
+    entities.push(
+        commands
+            .spawn(SpriteBundle {
+                texture: textures.player.clone(),
+                transform: position_to_transform(arena.player_spawn.clone()),
+                sprite: create_top_left_sprite(),
+                ..default()
+            })
+            .id(),
+    );
+
+    // カーソル。置いた範囲の角も表示する
+    let cursor_width = if editor.tool == Tool::Player {
+        PLAYER_WIDTH
+    } else {
+        1
+    };
+    for (position, width) in editor
+        .area_corner
+        .iter()
+        .map(|corner| (corner, 1))
+        .chain([(&editor.cursor, cursor_width)])
+    {
+        let mut cursor_pos = position_to_transform(position.clone());
+        cursor_pos.translation.z = 6.0;
+        entities.push(spawn_rect(
+            cursor_pos,
+            width,
+            1,
+            Color::rgba(1.0, 1.0, 0.0, 0.5),
+            &mut commands,
+        ));
+    }
+
+    let status = format!(
+        "{}  {}  X {} Y {}  {}",
+        editor.tool.label(),
+        editor.target_kind.to_ascii_uppercase(),
+        editor.cursor.x,
+        editor.cursor.y,
+        editor.message
+    );
+    entities.push(spawn_text(
+        &status,
+        arena.x_min,
+        arena.y_max + 2,
+        Color::WHITE,
+        &mut commands,
+    ));
+    entities.push(spawn_text(HELP, 1, 0, Color::WHITE, &mut commands));
+
+    for entity in entities {
+        commands.entity(entity).insert(EditorScreen);
+    }
+}
+
+// 左上がtransformの位置にある、columns×rowsセルの色付きの矩形
+fn spawn_rect(
+    transform: Transform,
+    columns: i32,
+    rows: i32,
+    color: Color,
+    commands: &mut Commands,
+) -> Entity {
+    commands
+        .spawn(SpriteBundle {
+            sprite: Sprite {
+                color,
+                custom_size: Some(Vec2::new(
+                    CELL_SIZE_PX * columns as f32,
+                    CELL_SIZE_PX * rows as f32,
+                )),
+                ..create_top_left_sprite()
+            },
+            transform,
+            ..default()
+        })
+        .id()
+}
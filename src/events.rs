@@ -4,6 +4,14 @@ use bevy::prelude::*;
 #[derive(Event, Default)]
 pub struct HitEvent;
 
+// ターゲットを壊して得点が入った
+#[derive(Event)]
+pub struct ScoreEvent {
+    pub pos: Position,
+    pub points: i32,
+    pub multiplier: i32,
+}
+
 #[derive(Event, Default)]
 pub struct CrashEvent {
     pub pos: Position,
@@ -0,0 +1,141 @@
+use bevy::prelude::*;
+use bevy::transform::TransformSystem;
+
+use crate::assets::*;
+use crate::graphics::create_top_left_sprite;
+use crate::resources::*;
+
+// 文字列の描画
+// BitmapTextを付けたentityのTransformを基準に、1文字ずつ子entityのスプライトを作る
+// 数字はnumbers.pngで、それ以外はfont.pngで描く
+// 英小文字は大文字で描き、font.pngにない文字は空白にする
+#[derive(Component, Debug, Clone, PartialEq)]
+pub struct BitmapText {
+    pub text: String,
+    pub color: Color,
+    pub align: TextAlign,
+}
+
+// Transformのxを文字列の左端・中央・右端のどれに揃えるか
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TextAlign {
+    #[default]
+    Left,
+    Center,
+    Right,
+}
+
+impl BitmapText {
+    pub fn new(text: impl Into<String>, color: Color) -> Self {
+        BitmapText {
+            text: text.into(),
+            color,
+            align: TextAlign::Left,
+        }
+    }
+
+    pub fn aligned(mut self, align: TextAlign) -> Self {
+        self.align = align;
+        self
+    }
+
+    // 1文字は半セル幅
+    pub fn width(&self) -> f32 {
+        IMAGE_FONT_TILE_SIZE.x * self.text.chars().count() as f32
+    }
+
+    // 1文字目の左端のTransformからのずれ
+    fn left(&self) -> f32 {
+        match self.align {
+            TextAlign::Left => 0.0,
+            TextAlign::Center => -(self.width() / 2.0).floor(),
+            TextAlign::Right => -self.width(),
+        }
+    }
+}
+
+// 今の子entityを作ったときの文字列と揃え方。色だけが変わったときは作り直さない
+#[derive(Component)]
+struct RenderedText(String, TextAlign);
+
+pub struct FontPlugin;
+
+impl Plugin for FontPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            PostUpdate,
+            bitmap_text_system.before(TransformSystem::TransformPropagate),
+        );
+    }
+}
+
+// 文字のfont.pngでの添字
+pub fn glyph_index(c: char) -> Option<usize> {
+    let index = (c.to_ascii_uppercase() as usize).checked_sub(FONT_FIRST_CHAR as usize)?;
+    (index < IMAGE_FONT_TILE_COLUMNS * IMAGE_FONT_TILE_ROWS).then_some(index)
+}
+
+// 文字を描く画像と添字。空白とfont.pngにない文字はNone
+fn glyph(
+    c: char,
+    textures: &Textures,
+) -> Option<(&Handle<Image>, &Handle<TextureAtlasLayout>, usize)> {
+    if let Some(digit) = c.to_digit(10) {
+        return Some((&textures.numbers, &textures.numbers_layout, digit as usize));
+    }
+    glyph_index(c)
+        .filter(|index| *index != 0)
+        .map(|index| (&textures.font, &textures.font_layout, index))
+}
+
+fn bitmap_text_system(
+    mut commands: Commands,
+    query: Query<
+        (
+            Entity,
+            &BitmapText,
+            Option<&RenderedText>,
+            Option<&Children>,
+        ),
+        Changed<BitmapText>,
+    >,
+    mut sprite_query: Query<&mut Sprite>,
+    textures: Res<Textures>,
+) {
+    for (entity, text, rendered, children) in &query {
+        if rendered.is_some_and(|rendered| rendered.0 == text.text && rendered.1 == text.align) {
+            for child in children.into_iter().flatten() {
+                if let Ok(mut sprite) = sprite_query.get_mut(*child) {
+                    sprite.color = text.color;
+                }
+            }
+            continue;
+        }
+
+        let mut entity_commands = commands.entity(entity);
+        entity_commands
+            .despawn_descendants()
+            .insert(RenderedText(text.text.clone(), text.align));
+        entity_commands.with_children(|parent| {
+            let mut x = text.left();
+            for c in text.text.chars() {
+                if let Some((texture, layout, index)) = glyph(c, &textures) {
+                    parent.spawn(SpriteSheetBundle {
+                        texture: texture.clone(),
+                        atlas: TextureAtlas {
+                            layout: layout.clone(),
+                            index,
+                        },
+                        transform: Transform::from_xyz(x, 0.0, 0.0),
+                        sprite: Sprite {
+                            color: text.color,
+                            ..create_top_left_sprite()
+                        },
+                        ..default()
+                    });
+                }
+                x += IMAGE_FONT_TILE_SIZE.x;
+            }
+        });
+    }
+}
@@ -0,0 +1,107 @@
+use std::collections::HashSet;
+
+use bevy::input::gamepad::GamepadConnectionEvent;
+use bevy::prelude::*;
+
+use crate::actions::*;
+use crate::states::*;
+
+// 左スティックをこれ以上倒したら左右の移動とみなす
+const STICK_THRESHOLD: f32 = 0.5;
+
+const GAMEPAD_BUTTONS: &[(GamepadButtonType, Action)] = &[
+    (GamepadButtonType::DPadLeft, Action::MoveLeft),
+    (GamepadButtonType::DPadRight, Action::MoveRight),
+    (GamepadButtonType::South, Action::Fire),
+    (GamepadButtonType::West, Action::Fire),
+    (GamepadButtonType::RightTrigger, Action::SwitchWeapon),
+    (GamepadButtonType::LeftTrigger, Action::SwitchWeapon),
+    (GamepadButtonType::North, Action::Restart),
+    (GamepadButtonType::East, Action::Restart),
+    (GamepadButtonType::Start, Action::Pause),
+];
+
+// 使っているゲームパッド。1人プレイなので1つだけ使う
+// 最初につながったものを使い、外れたらつながっている別のゲームパッドに替える
+#[derive(Resource, Default)]
+pub struct ActiveGamepad(pub Option<Gamepad>);
+
+// ゲームパッドの入力を操作に変換する
+// 十字キーと左スティックで移動、A/Xで発射、LB/RBで武器の切り替え、Y/Bでリスタート、Startでポーズ
+pub struct GamepadPlugin;
+
+impl Plugin for GamepadPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ActiveGamepad>().add_systems(
+            PreUpdate,
+            (gamepad_connection_system, gamepad_action_system)
+                .chain()
+                .after(action_state_system),
+        );
+    }
+}
+
+fn gamepad_connection_system(
+    mut connection_events: EventReader<GamepadConnectionEvent>,
+    gamepads: Res<Gamepads>,
+    mut active: ResMut<ActiveGamepad>,
+    state: Res<State<GameState>>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    for event in connection_events.read() {
+        if event.connected() {
+            if active.0.is_none() {
+                active.0 = Some(event.gamepad);
+                info!("using gamepad {}", event.gamepad.id);
+            }
+        } else if active.0 == Some(event.gamepad) {
+            info!("gamepad {} disconnected", event.gamepad.id);
+            // プレイ中に外れたらポーズする
+            if *state.get() == GameState::Playing {
+                next_state.set(GameState::Paused);
+            }
+            // つながっている別のゲームパッドがあれば代わりに使う
+            active.0 = gamepads.iter().find(|gamepad| *gamepad != event.gamepad);
+            if let Some(gamepad) = active.0 {
+                info!("using gamepad {}", gamepad.id);
+            }
+        }
+    }
+}
+
+// 使っているゲームパッドの入力をActionStateに加える
+fn gamepad_action_system(
+    active: Res<ActiveGamepad>,
+    buttons: Res<ButtonInput<GamepadButton>>,
+    axes: Res<Axis<GamepadAxis>>,
+    mut actions: ResMut<ActionState>,
+    mut stick_pressed: Local<HashSet<Action>>,
+) {
+    let Some(gamepad) = active.0 else {
+        stick_pressed.clear();
+        return;
+    };
+
+    for (button_type, action) in GAMEPAD_BUTTONS {
+        let button = GamepadButton::new(gamepad, *button_type);
+        if buttons.pressed(button) {
+            actions.press(*action, buttons.just_pressed(button));
+        }
+    }
+
+    // スティックは倒し始めたフレームを押した瞬間とみなす
+    let x = axes
+        .get(GamepadAxis::new(gamepad, GamepadAxisType::LeftStickX))
+        .unwrap_or(0.0);
+    let mut pressed = HashSet::new();
+    if x <= -STICK_THRESHOLD {
+        pressed.insert(Action::MoveLeft);
+    }
+    if x >= STICK_THRESHOLD {
+        pressed.insert(Action::MoveRight);
+    }
+    for action in &pressed {
+        actions.press(*action, !stick_pressed.contains(action));
+    }
+    *stick_pressed = pressed;
+}
@@ -0,0 +1,1090 @@
+use std::collections::HashSet;
+
+use bevy::prelude::*;
+use rand::Rng;
+
+use crate::actions::*;
+use crate::arena::*;
+use crate::components::{self, *};
+use crate::events::*;
+use crate::level::*;
+use crate::resources::*;
+use crate::states::*;
+use crate::{LIVES, TICK_RATE};
+
+// ゲームロジックのsystemが属するset
+// 描画・音・HUDはこのsetの後に実行する
+#[derive(Debug, Clone, PartialEq, Eq, Hash, SystemSet)]
+pub struct GameplaySet;
+
+// そのtickのPlayerIntentを決めるsystemが属するset
+// GameplaySetの中で、ゲームロジックより先に実行する
+#[derive(Debug, Clone, PartialEq, Eq, Hash, SystemSet)]
+pub struct InputSet;
+
+#[derive(Default)]
+pub struct GameplayPlugin {
+    // 指定しなければ起動ごとにランダムなseedを使う
+    pub seed: Option<u64>,
+    pub initial_state: GameState,
+    // 1秒あたりのtick数。指定しなければTICK_RATE
+    pub tick_rate: Option<f64>,
+    // ゲームを始めるときの残機。指定しなければLIVES
+    pub lives: Option<u32>,
+    pub levels: Levels,
+}
+
+// 面をクリアしてから次の面に進むまでの時間
+const LEVEL_CLEAR_SECONDS: f32 = 2.0;
+// やられてからプレイヤーを出し直すまでのtick数
+const RESPAWN_TICKS: u32 = 30;
+// 出し直したプレイヤーが弾に当たらないtick数
+const INVULNERABLE_TICKS: u32 = 60;
+// 出し直すときに、出現位置からこのセル数以内の弾を消す
+const RESPAWN_CLEAR_CELLS: i32 = 4;
+// 連鎖が終わったときのボーナスの単位。コンボ数nでn(n-1)倍
+const CHAIN_BONUS: i32 = 250;
+// 跳ね返り続ける弾があっても、このtick数ターゲットを壊さなかった連鎖は終わりにする
+const CHAIN_IDLE_TICKS: u32 = 45;
+
+#[derive(Resource)]
+struct LevelClearTimer(Timer);
+
+impl Plugin for GameplayPlugin {
+    fn build(&self, app: &mut App) {
+        let seed = self.seed.unwrap_or_else(|| rand::thread_rng().gen());
+        // ゲームロジックは描画のフレームレートとは関係なく、FixedUpdateで一定の間隔で進める
+        app.insert_resource(Time::<Fixed>::from_hz(self.tick_rate.unwrap_or(TICK_RATE)))
+            .insert_state(self.initial_state)
+            .insert_resource(self.levels.screen())
+            .insert_resource(self.levels.get(0).arena.clone())
+            .insert_resource(self.levels.clone())
+            .insert_resource(LevelClearTimer(Timer::from_seconds(
+                LEVEL_CLEAR_SECONDS,
+                TimerMode::Once,
+            )))
+            .insert_resource(LivesRule {
+                lives: self.lives.unwrap_or(LIVES).max(1),
+            })
+            .init_resource::<Game>()
+            .init_resource::<PlayerIntent>()
+            .init_resource::<Occupancy>()
+            .insert_resource(GameRng::new(seed))
+            .add_event::<HitEvent>()
+            .add_event::<ScoreEvent>()
+            .add_event::<CrashEvent>()
+            .init_resource::<Resuming>()
+            .init_resource::<Advancing>()
+            .init_resource::<Playtesting>()
+            .add_systems(
+                OnTransition {
+                    from: GameState::Paused,
+                    to: GameState::Playing,
+                },
+                |mut resuming: ResMut<Resuming>| resuming.0 = true,
+            )
+            .add_systems(
+                OnEnter(GameState::Playing),
+                (playing_enter, spawn_level)
+                    .chain()
+                    .in_set(GameplaySet)
+                    .run_if(not_resuming),
+            )
+            .add_systems(
+                OnEnter(GameState::Attract),
+                (playing_enter, spawn_level).chain().in_set(GameplaySet),
+            )
+            .add_systems(OnExit(GameState::Playing), playing_exit)
+            .configure_sets(FixedUpdate, InputSet.in_set(GameplaySet))
+            .add_systems(
+                FixedUpdate,
+                action_intent_system
+                    .in_set(InputSet)
+                    .run_if(in_state(GameState::Playing)),
+            )
+            .add_systems(
+                FixedUpdate,
+                (
+                    tick_system,
+                    respawn_system,
+                    invulnerable_system,
+                    player_system,
+                    weapon_system,
+                    bullet_system,
+                    target_spawn_system,
+                    occupancy_system,
+                    collision_bullet_target_system,
+                    pending_burst_system,
+                    collision_bullet_bullet_system,
+                    collision_player_bullet_system,
+                    chain_end_system,
+                    extra_life_system,
+                    level_clear_system,
+                    crash_event,
+                    // 面のクリアやゲームオーバーで加えたボーナスも含める
+                    hi_score_system
+                        .run_if(in_state(GameState::Playing))
+                        .run_if(not_playtesting),
+                )
+                    .chain()
+                    .in_set(GameplaySet)
+                    .after(InputSet)
+                    .run_if(in_game),
+            )
+            .add_systems(Update, restart_system.run_if(in_state(GameState::GameOver)))
+            .add_systems(OnEnter(GameState::LevelClear), level_clear_enter)
+            .add_systems(
+                Update,
+                next_level_system.run_if(in_state(GameState::LevelClear)),
+            );
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn playing_enter(
+    mut commands: Commands,
+    mut actions: ResMut<ActionState>,
+    mut game: ResMut<Game>,
+    mut rng: ResMut<GameRng>,
+    advancing: Res<Advancing>,
+    lives_rule: Res<LivesRule>,
+    state: Res<State<GameState>>,
+    query: Query<(&DespawnOnRestart, Entity)>,
+) {
+    // 次の面に進むときはスコア・tick数・残機・乱数を引き継ぐ
+    // デモは--seedで決まるゲームのseedを使わない
+    if !advancing.0 {
+        game.reset(lives_rule.lives);
+        game.seed = if *state.get() == GameState::Attract {
+            rng.start_demo()
+        } else {
+            rng.start_game()
+        };
+    }
+    game.level_ticks = 0;
+    game.level_start_score = game.score;
+    game.chains.clear();
+    // ゲームを始めるために押したキーで撃たないようにする
+    actions.clear_latched();
+
+    for (_, entity) in &query {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+// 今の面の領域・ターゲット・プレイヤーを置く
+fn spawn_level(
+    mut commands: Commands,
+    game: Res<Game>,
+    levels: Res<Levels>,
+    mut arena: ResMut<Arena>,
+) {
+    let level = levels.get(game.level);
+    *arena = level.arena.clone();
+    for target in &level.targets {
+        let position = target.position();
+        commands.spawn((
+            Target::new(level.target_kind(&target.kind).unwrap_or_default()),
+            DespawnOnRestart,
+            PreviousPosition(position.clone()),
+            position,
+        ));
+    }
+
+    spawn_player(&mut commands, &arena);
+}
+
+fn spawn_player(commands: &mut Commands, arena: &Arena) -> Entity {
+    commands
+        .spawn((
+            Player,
+            DespawnOnRestart,
+            PreviousPosition(arena.player_spawn.clone()),
+            arena.player_spawn.clone(),
+            Weapon::new(WeaponKind::Single),
+        ))
+        .id()
+}
+
+// やられてから時間がたったら、出現位置の近くの弾を消してプレイヤーを出し直す
+fn respawn_system(
+    mut commands: Commands,
+    mut query: Query<(&mut Respawning, Entity)>,
+    bullets_query: Query<(&Position, Entity), With<Bullet>>,
+    arena: Res<Arena>,
+) {
+    for (mut respawning, entity) in &mut query {
+        if respawning.ticks > 0 {
+            respawning.ticks -= 1;
+            continue;
+        }
+        commands.entity(entity).despawn();
+        let spawn = &arena.player_spawn;
+        for (position, bullet) in &bullets_query {
+            if position.x >= spawn.x - RESPAWN_CLEAR_CELLS
+                && position.x <= spawn.x + PLAYER_WIDTH - 1 + RESPAWN_CLEAR_CELLS
+                && position.y >= spawn.y - RESPAWN_CLEAR_CELLS
+            {
+                commands.entity(bullet).despawn();
+            }
+        }
+        let player = spawn_player(&mut commands, &arena);
+        commands.entity(player).insert(Invulnerable {
+            ticks: INVULNERABLE_TICKS,
+        });
+    }
+}
+
+fn invulnerable_system(mut commands: Commands, mut query: Query<(&mut Invulnerable, Entity)>) {
+    for (mut invulnerable, entity) in &mut query {
+        invulnerable.ticks = invulnerable.ticks.saturating_sub(1);
+        if invulnerable.ticks == 0 {
+            commands.entity(entity).remove::<Invulnerable>();
+        }
+    }
+}
+
+// スコアが決まった値に達するたびに残機を1つ増やす
+fn extra_life_system(mut game: ResMut<Game>) {
+    while game.score >= game.next_extra_life {
+        game.lives += 1;
+        game.next_extra_life += EXTRA_LIFE_INTERVAL;
+    }
+}
+
+fn playing_exit(mut resuming: ResMut<Resuming>, mut advancing: ResMut<Advancing>) {
+    resuming.0 = false;
+    advancing.0 = false;
+}
+
+// tickの間に一瞬だけ押されたものも拾う
+pub fn action_intent_system(mut actions: ResMut<ActionState>, mut intent: ResMut<PlayerIntent>) {
+    let mut input = |action| actions.take_latched(action) | actions.pressed(action);
+    *intent = PlayerIntent {
+        left: input(Action::MoveLeft),
+        right: input(Action::MoveRight),
+        fire: input(Action::Fire),
+        switch_weapon: actions.take_latched(Action::SwitchWeapon),
+    };
+}
+
+fn player_system(
+    intent: Res<PlayerIntent>,
+    arena: Res<Arena>,
+    mut query: Query<(&mut Position, &mut PreviousPosition), With<Player>>,
+) {
+    for (mut position, mut previous) in &mut query {
+        previous.0 = position.clone();
+        // 左端の左か右端の右が壁なら動けない
+        if intent.left && !arena.is_wall(&position.add(-1, 0)) {
+            position.x -= 1;
+        }
+
+        if intent.right && !arena.is_wall(&position.add(PLAYER_WIDTH, 0)) {
+            position.x += 1;
+        }
+    }
+}
+
+// 武器の切り替えと発射
+// 撃つとcooldownの間は撃てない。burstが2以上ならburst_intervalごとに続けて撃つ
+fn weapon_system(
+    intent: Res<PlayerIntent>,
+    mut game: ResMut<Game>,
+    arena: Res<Arena>,
+    mut query: Query<(&Position, &mut Weapon), With<Player>>,
+    shots_query: Query<(), With<PlayerShot>>,
+    mut commands: Commands,
+) {
+    for (position, mut weapon) in &mut query {
+        if intent.switch_weapon {
+            // 次の使える武器にする
+            let kinds = WeaponKind::all();
+            let current = kinds.iter().position(|k| *k == weapon.kind).unwrap_or(0);
+            let next = (1..=kinds.len())
+                .map(|i| kinds[(current + i) % kinds.len()])
+                .find(|k| k.unlock_score() <= game.score);
+            if let Some(kind) = next {
+                if kind != weapon.kind {
+                    *weapon = Weapon::new(kind);
+                }
+            }
+        }
+
+        if weapon.timer > 0 {
+            weapon.timer -= 1;
+        }
+        if weapon.timer > 0 {
+            continue;
+        }
+        if weapon.burst_left == 0 {
+            if !intent.fire {
+                continue;
+            }
+            weapon.burst_left = weapon.burst;
+        }
+
+        let pattern = weapon.kind.pattern();
+        if shots_query.iter().count() + pattern.len() > weapon.max_bullets {
+            // 弾が多すぎるときはバーストをやめる
+            weapon.burst_left = 0;
+            continue;
+        }
+        // 1回に撃つ弾は同じ連鎖にする
+        let chain = game.chains.start();
+        for (dx, dy, direction) in pattern {
+            let bullet_position = position.add(*dx, *dy);
+            // 壁の中には撃たない。斜めに壁に向かって撃った弾は跳ね返す
+            if arena.is_wall(&bullet_position) {
+                continue;
+            }
+            let direction = bounce(direction, &bullet_position, |p| arena.is_wall(p));
+            let bullet = spawn_bullet(&mut commands, &bullet_position, direction, false, chain);
+            commands.entity(bullet).insert(PlayerShot);
+        }
+        weapon.burst_left -= 1;
+        weapon.timer = if weapon.burst_left > 0 {
+            weapon.burst_interval
+        } else {
+            weapon.cooldown
+        };
+    }
+}
+
+fn tick_system(mut game: ResMut<Game>) {
+    game.ticks += 1;
+    game.level_ticks += 1;
+}
+
+fn restart_system(mut next_state: ResMut<NextState<GameState>>, actions: Res<ActionState>) {
+    if actions.just_pressed(Action::Restart) {
+        next_state.set(GameState::Playing);
+    }
+}
+
+pub fn spawn_bullet(
+    commands: &mut Commands,
+    bullet_position: &Position,
+    direction: components::Direction,
+    is_dust: bool,
+    chain: Chain,
+) -> Entity {
+    let mut bullet = commands.spawn((
+        Bullet,
+        chain,
+        DespawnOnRestart,
+        PreviousPosition(bullet_position.clone()),
+        bullet_position.clone(),
+        direction,
+    ));
+    if is_dust {
+        bullet.insert(Dust);
+    }
+    bullet.id()
+}
+
+fn bullet_system(
+    mut query: Query<
+        (
+            &mut Position,
+            &mut PreviousPosition,
+            &mut components::Direction,
+            Entity,
+        ),
+        With<Bullet>,
+    >,
+    arena: Res<Arena>,
+    mut commands: Commands,
+) {
+    for (mut pos, mut previous, mut dir, entity) in &mut query {
+        previous.0 = pos.clone();
+        // 置かれた壁の中には入らない
+        let bounced = bounce(&dir, &pos, |p| arena.is_placed_wall(p));
+        if bounced != *dir {
+            *dir = bounced;
+        }
+        *pos = dir.neighbor(pos.clone());
+        // 下に抜けたら消え、次に壁に入るなら跳ね返る
+        if pos.y > arena.y_max {
+            commands.entity(entity).despawn();
+        } else {
+            let bounced = bounce(&dir, &pos, |p| arena.is_wall(p));
+            if bounced != *dir {
+                *dir = bounced;
+            }
+        }
+    }
+}
+
+// posからdirに進むと塞がったセルに入るなら、跳ね返った向きを返す
+// 斜めの弾は、横だけが塞がっていれば左右、縦だけなら上下を反転し、
+// 角に当たったときや反転しても塞がっているときは来た方向に戻る
+fn bounce(
+    dir: &components::Direction,
+    pos: &Position,
+    is_blocked: impl Fn(&Position) -> bool,
+) -> components::Direction {
+    if !is_blocked(&dir.neighbor(pos.clone())) {
+        return dir.clone();
+    }
+    if !dir.is_diagonal() {
+        return dir.opposite();
+    }
+    let (dx, dy) = dir.delta();
+    let reflected = match (is_blocked(&pos.add(dx, 0)), is_blocked(&pos.add(0, dy))) {
+        (true, false) => dir.reflect(true, false),
+        (false, true) => dir.reflect(false, true),
+        _ => dir.opposite(),
+    };
+    if is_blocked(&reflected.neighbor(pos.clone())) {
+        dir.opposite()
+    } else {
+        reflected
+    }
+}
+
+// 弾も時間差の破裂も残っていないか、しばらくターゲットを壊していない連鎖を終わりにして、
+// コンボ数に応じたボーナスを加える
+fn chain_end_system(
+    mut game: ResMut<Game>,
+    bullets_query: Query<&Chain, With<Bullet>>,
+    bursts_query: Query<&PendingBurst>,
+) {
+    let alive: HashSet<u32> = bullets_query
+        .iter()
+        .chain(bursts_query.iter().map(|burst| &burst.chain))
+        .map(|chain| chain.id)
+        .collect();
+    let ended: Vec<u32> = game
+        .chains
+        .combos()
+        .filter(|(id, combo)| {
+            !alive.contains(id) || game.ticks >= combo.last_tick + CHAIN_IDLE_TICKS
+        })
+        .map(|(id, _)| id)
+        .collect();
+    for id in ended {
+        let combo = game.chains.end(id);
+        game.score += chain_bonus(combo);
+    }
+}
+
+// 続いている連鎖をすべて終わりにしてボーナスを加える
+fn end_all_chains(game: &mut Game) {
+    for combo in game.chains.end_all() {
+        game.score += chain_bonus(combo);
+    }
+}
+
+fn chain_bonus(combo: u32) -> i32 {
+    let combo = combo as i32;
+    CHAIN_BONUS * combo * (combo - 1)
+}
+
+// デモや試しに遊んだゲームの得点はハイスコアにしない
+fn hi_score_system(mut game: ResMut<Game>) {
+    if game.score > game.hi_score {
+        game.hi_score = game.score;
+    }
+}
+
+// 面のクリア条件を満たしたら次の面に進む
+// デモは1面だけでタイトル画面に戻る
+fn level_clear_system(
+    mut game: ResMut<Game>,
+    levels: Res<Levels>,
+    targets_query: Query<(), With<Target>>,
+    state: Res<State<GameState>>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    let cleared = match levels.get(game.level).win {
+        WinCondition::Endless => false,
+        WinCondition::ClearTargets => targets_query.is_empty(),
+        WinCondition::Score(score) => game.level_score() >= score,
+        WinCondition::Survive(ticks) => game.level_ticks >= ticks,
+    };
+    if !cleared {
+        return;
+    }
+    // 続いている連鎖はここで終わりにしてボーナスを加える
+    end_all_chains(&mut game);
+    if *state.get() == GameState::Attract {
+        next_state.set(GameState::Title);
+    } else {
+        next_state.set(GameState::LevelClear);
+    }
+}
+
+fn level_clear_enter(mut timer: ResMut<LevelClearTimer>) {
+    timer.0.reset();
+}
+
+// 少し待ってから次の面を始める。最後の面ならゲームオーバー
+fn next_level_system(
+    time: Res<Time>,
+    mut timer: ResMut<LevelClearTimer>,
+    mut game: ResMut<Game>,
+    levels: Res<Levels>,
+    mut advancing: ResMut<Advancing>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if !timer.0.tick(time.delta()).just_finished() {
+        return;
+    }
+    if levels.is_last(game.level) {
+        next_state.set(GameState::GameOver);
+    } else {
+        game.level += 1;
+        advancing.0 = true;
+        next_state.set(GameState::Playing);
+    }
+}
+
+// 残機が残っていればプレイヤーを出し直し、なくなったらゲームオーバー
+// デモは1回やられたらタイトル画面に戻る
+fn crash_event(
+    mut commands: Commands,
+    state: Res<State<GameState>>,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut crash_events: EventReader<CrashEvent>,
+    mut game: ResMut<Game>,
+) {
+    if crash_events.is_empty() {
+        return;
+    }
+    crash_events.clear();
+    if *state.get() == GameState::Attract {
+        next_state.set(GameState::Title);
+        return;
+    }
+    game.lives = game.lives.saturating_sub(1);
+    if game.lives > 0 {
+        commands.spawn((
+            Respawning {
+                ticks: RESPAWN_TICKS,
+            },
+            DespawnOnRestart,
+        ));
+    } else {
+        end_all_chains(&mut game);
+        next_state.set(GameState::GameOver);
+    }
+}
+
+fn target_spawn_system(
+    mut commands: Commands,
+    query: Query<(&Target, &Position)>,
+    mut rng: ResMut<GameRng>,
+    arena: Res<Arena>,
+    game: Res<Game>,
+    levels: Res<Levels>,
+) {
+    let level = levels.get(game.level);
+    let rule = &level.spawn;
+    if rule.max_targets == 0 {
+        return;
+    }
+    let area = &arena.target_area;
+    let position = Position::new(
+        rng.gen_range(area.x_min..=area.x_max),
+        rng.gen_range(area.y_min..=area.y_max),
+    );
+    if arena.is_placed_wall(&position) {
+        return;
+    }
+    let mut target_count = 0;
+    for (_, pos) in &query {
+        target_count += 1;
+        if *pos == position {
+            // 既存のターゲットと重なる場合は生成しない
+            return;
+        }
+    }
+    if !(rng.gen_bool(rule.chance) && target_count < rule.max_targets) {
+        return;
+    }
+    let kind = pick_target_kind(&rule.kinds, &mut rng);
+    commands.spawn((
+        Target::new(level.target_kind(kind).unwrap_or_default()),
+        DespawnOnRestart,
+        PreviousPosition(position.clone()),
+        position,
+    ));
+}
+
+// 重みに従って種類を選ぶ。種類がなければ乱数を使わずにnormalにする
+fn pick_target_kind<'a>(kinds: &'a [(String, u32)], rng: &mut GameRng) -> &'a str {
+    let total: u32 = kinds.iter().map(|(_, weight)| weight).sum();
+    if total == 0 {
+        return DEFAULT_TARGET_KIND;
+    }
+    let mut n = rng.gen_range(0..total);
+    for (name, weight) in kinds {
+        if n < *weight {
+            return name;
+        }
+        n -= weight;
+    }
+    DEFAULT_TARGET_KIND
+}
+
+// 衝突判定に使うセルごとの弾とターゲットを作り直す
+fn occupancy_system(
+    mut occupancy: ResMut<Occupancy>,
+    bullets_query: Query<(&Position, Entity), With<Bullet>>,
+    targets_query: Query<(&Position, Entity), With<Target>>,
+) {
+    occupancy.clear();
+    for (position, entity) in &bullets_query {
+        occupancy.add_bullet(position, entity);
+    }
+    for (position, entity) in &targets_query {
+        occupancy.add_target(position, entity);
+    }
+}
+
+// 1tickの間にaがa0からa1へ、bがb0からb1へ動いたとき、途中で出会ったか
+// 1tickには隣のセルまでしか動かないので、同じセルに着いたか、
+// 移動の中点が同じ(入れ替わった、または斜めに交差した)かを見ればよい
+fn paths_meet(a0: &Position, a1: &Position, b0: &Position, b1: &Position) -> bool {
+    a1 == b1 || (a0.x + a1.x == b0.x + b1.x && a0.y + a1.y == b0.y + b1.y)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn collision_bullet_target_system(
+    mut commands: Commands,
+    bullets_query: Query<
+        (&Position, &PreviousPosition, &Chain, Entity),
+        (With<Bullet>, Without<Target>),
+    >,
+    mut targets_query: Query<(&Position, &PreviousPosition, &mut Target), Without<Bullet>>,
+    mut occupancy: ResMut<Occupancy>,
+    mut hit_events: EventWriter<HitEvent>,
+    mut score_events: EventWriter<ScoreEvent>,
+    mut game: ResMut<Game>,
+    arena: Res<Arena>,
+) {
+    for (bullet_pos, bullet_prev, chain, bullet_entity) in &bullets_query {
+        let hit = occupancy
+            .targets_near(bullet_pos)
+            .find_map(|target_entity| {
+                let (target_pos, target_prev, _) = targets_query.get(target_entity).ok()?;
+                paths_meet(&bullet_prev.0, bullet_pos, &target_prev.0, target_pos)
+                    .then_some(target_entity)
+            });
+        let Some(target_entity) = hit else {
+            continue;
+        };
+        let Ok((target_pos, _, mut target)) = targets_query.get_mut(target_entity) else {
+            continue;
+        };
+        commands.entity(bullet_entity).despawn();
+        occupancy.remove_bullet(bullet_pos, bullet_entity);
+        hit_events.send_default();
+        // HPが残っていれば弾だけ消える
+        target.hp -= 1;
+        if target.hp > 0 {
+            continue;
+        }
+        commands.entity(target_entity).despawn();
+        occupancy.remove_target(target_pos, target_entity);
+        // 連鎖の段が深いほど倍率が上がる
+        let points = target.kind.score * chain.multiplier();
+        game.score += points;
+        score_events.send(ScoreEvent {
+            pos: target_pos.clone(),
+            points,
+            multiplier: chain.multiplier(),
+        });
+        let ticks = game.ticks;
+        game.chains.add_combo(chain, ticks);
+        for burst in target.kind.explosion.bursts() {
+            if burst.delay == 0 {
+                spawn_shots(
+                    &mut commands,
+                    &mut occupancy,
+                    &arena,
+                    target_pos,
+                    &burst.shots,
+                    chain.next(),
+                );
+            } else {
+                commands.spawn((
+                    PendingBurst {
+                        tick: game.ticks + burst.delay,
+                        shots: burst.shots,
+                        chain: chain.next(),
+                    },
+                    DespawnOnRestart,
+                    target_pos.clone(),
+                ));
+            }
+        }
+    }
+}
+
+// 時間差の破裂の時間になったら弾を出す
+fn pending_burst_system(
+    mut commands: Commands,
+    query: Query<(&PendingBurst, &Position, Entity)>,
+    mut occupancy: ResMut<Occupancy>,
+    arena: Res<Arena>,
+    game: Res<Game>,
+) {
+    for (burst, position, entity) in &query {
+        if game.ticks < burst.tick {
+            continue;
+        }
+        commands.entity(entity).despawn();
+        spawn_shots(
+            &mut commands,
+            &mut occupancy,
+            &arena,
+            position,
+            &burst.shots,
+            burst.chain,
+        );
+    }
+}
+
+// ターゲットがあったpositionから破裂の弾を出す
+// 途中に置かれた壁があれば出さない。2セル以上離す弾は領域の外にも出さない
+fn spawn_shots(
+    commands: &mut Commands,
+    occupancy: &mut Occupancy,
+    arena: &Arena,
+    position: &Position,
+    shots: &[Shot],
+    chain: Chain,
+) {
+    for shot in shots {
+        let mut cell = position.clone();
+        let mut blocked = false;
+        for step in 1..=shot.distance {
+            cell = shot.direction.neighbor(cell);
+            blocked |= if step == 1 {
+                arena.is_placed_wall(&cell)
+            } else {
+                arena.is_wall(&cell) || cell.y > arena.y_max
+            };
+        }
+        if blocked {
+            continue;
+        }
+        // 斜めの弾は、壁に向かっていれば出したときに跳ね返す
+        let direction = if shot.direction.is_diagonal() {
+            bounce(&shot.direction, &cell, |p| arena.is_wall(p))
+        } else {
+            shot.direction.clone()
+        };
+        let bullet = spawn_bullet(commands, &cell, direction, shot.dust, chain);
+        occupancy.add_bullet(&cell, bullet);
+    }
+}
+
+// 左右(斜めも)にすれ違う弾は、同じセルに入ったときも隣同士で入れ替わったときも消える
+// 上下にだけ動く弾同士は消えない
+fn collision_bullet_bullet_system(
+    mut commands: Commands,
+    bullets_query: Query<
+        (&Position, &PreviousPosition, &components::Direction, Entity),
+        With<Bullet>,
+    >,
+    mut occupancy: ResMut<Occupancy>,
+) {
+    for (bullet_pos, bullet_prev, dir, bullet_entity) in &bullets_query {
+        let (dx, dy) = movement(&bullet_prev.0, bullet_pos, dir);
+        if dx == 0 {
+            continue;
+        }
+        if !occupancy.bullets_at(bullet_pos).contains(&bullet_entity) {
+            continue;
+        }
+        let hits: Vec<(Entity, &Position)> = occupancy
+            .bullets_near(bullet_pos)
+            .filter_map(|other| {
+                let (other_pos, other_prev, other_dir, _) = bullets_query.get(other).ok()?;
+                (movement(&other_prev.0, other_pos, other_dir) == (-dx, -dy)
+                    && paths_meet(&bullet_prev.0, bullet_pos, &other_prev.0, other_pos))
+                .then_some((other, other_pos))
+            })
+            .collect();
+        if hits.is_empty() {
+            continue;
+        }
+        commands.entity(bullet_entity).despawn();
+        occupancy.remove_bullet(bullet_pos, bullet_entity);
+        for (other, other_pos) in hits {
+            commands.entity(other).despawn();
+            occupancy.remove_bullet(other_pos, other);
+        }
+    }
+}
+
+// そのtickに弾が動いた向き
+// Directionは壁で跳ね返ると動いた後に変わるので、動く前と後の位置から求める
+// 出たばかりでまだ動いていない弾はDirectionの向き
+fn movement(previous: &Position, position: &Position, dir: &components::Direction) -> (i32, i32) {
+    match (position.x - previous.x, position.y - previous.y) {
+        (0, 0) => dir.delta(),
+        delta => delta,
+    }
+}
+
+fn collision_player_bullet_system(
+    mut commands: Commands,
+    players_query: Query<
+        (&Position, &PreviousPosition, Entity),
+        (With<Player>, Without<Invulnerable>),
+    >,
+    bullets_query: Query<(&Position, &PreviousPosition), (With<Bullet>, Without<Player>)>,
+    mut occupancy: ResMut<Occupancy>,
+    mut crash_events: EventWriter<CrashEvent>,
+) {
+    for (player_pos, player_prev, player_entity) in &players_query {
+        let mut hits: Vec<(Entity, &Position)> = Vec::new();
+        // セルごとに移動経路を見る
+        for dx in 0..PLAYER_WIDTH {
+            let cell = player_pos.add(dx, 0);
+            let cell_prev = player_prev.0.add(dx, 0);
+            for bullet_entity in occupancy.bullets_near(&cell) {
+                let Ok((bullet_pos, bullet_prev)) = bullets_query.get(bullet_entity) else {
+                    continue;
+                };
+                if paths_meet(&cell_prev, &cell, &bullet_prev.0, bullet_pos)
+                    && !hits.iter().any(|(e, _)| *e == bullet_entity)
+                {
+                    hits.push((bullet_entity, bullet_pos));
+                }
+            }
+        }
+        if hits.is_empty() {
+            continue;
+        }
+        for (bullet_entity, bullet_pos) in hits {
+            commands.entity(bullet_entity).despawn();
+            occupancy.remove_bullet(bullet_pos, bullet_entity);
+        }
+        commands.entity(player_entity).despawn();
+        crash_events.send(CrashEvent {
+            pos: player_pos.clone(),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paths_meet_in_same_cell() {
+        let a0 = Position::new(4, 5);
+        let b0 = Position::new(6, 5);
+        let cell = Position::new(5, 5);
+        assert!(paths_meet(&a0, &cell, &b0, &cell));
+    }
+
+    #[test]
+    fn paths_meet_when_swapping_cells() {
+        let a = Position::new(4, 5);
+        let b = Position::new(5, 5);
+        assert!(paths_meet(&a, &b, &b, &a));
+        // 縦に入れ替わる
+        let c = Position::new(4, 6);
+        assert!(paths_meet(&a, &c, &c, &a));
+    }
+
+    #[test]
+    fn paths_meet_when_crossing_diagonally() {
+        // aは右下へ、bは左下へ動いて、4つのセルの真ん中で交差する
+        let a0 = Position::new(4, 5);
+        let a1 = Position::new(5, 6);
+        let b0 = Position::new(5, 5);
+        let b1 = Position::new(4, 6);
+        assert!(paths_meet(&a0, &a1, &b0, &b1));
+    }
+
+    #[test]
+    fn paths_do_not_meet() {
+        // 同じ向きに並んで動く
+        let a0 = Position::new(4, 5);
+        let a1 = Position::new(5, 5);
+        let b0 = Position::new(5, 5);
+        let b1 = Position::new(6, 5);
+        assert!(!paths_meet(&a0, &a1, &b0, &b1));
+        // 隣の列ですれ違う
+        let b0 = Position::new(5, 4);
+        let b1 = Position::new(4, 4);
+        assert!(!paths_meet(&a0, &a1, &b0, &b1));
+        // 止まっているものの隣を通り過ぎる
+        let still = Position::new(5, 6);
+        assert!(!paths_meet(&a0, &a1, &still, &still));
+    }
+
+    #[test]
+    fn chain_bonus_grows_with_combo() {
+        assert_eq!(chain_bonus(0), 0);
+        assert_eq!(chain_bonus(1), 0);
+        assert_eq!(chain_bonus(2), CHAIN_BONUS * 2);
+        assert_eq!(chain_bonus(5), CHAIN_BONUS * 20);
+    }
+
+    fn chain_app(combo: u32) -> (App, Chain) {
+        let mut app = App::new();
+        let mut game = Game::default();
+        let chain = game.chains.start();
+        for _ in 0..combo {
+            game.chains.add_combo(&chain, 0);
+        }
+        app.insert_resource(game)
+            .add_systems(Update, chain_end_system);
+        (app, chain)
+    }
+
+    #[test]
+    fn chain_settles_when_its_bullets_are_gone() {
+        let (mut app, chain) = chain_app(3);
+        let bullet = app.world.spawn((Bullet, chain.next())).id();
+        app.update();
+        assert_eq!(app.world.resource::<Game>().chains.max_combo(), 3);
+        assert_eq!(app.world.resource::<Game>().score, 0);
+
+        app.world.despawn(bullet);
+        app.update();
+        let game = app.world.resource::<Game>();
+        assert_eq!(game.chains.max_combo(), 0);
+        assert_eq!(game.score, chain_bonus(3));
+    }
+
+    #[test]
+    fn chain_waits_for_pending_bursts() {
+        let (mut app, chain) = chain_app(2);
+        app.world.spawn(PendingBurst {
+            tick: 10,
+            shots: Vec::new(),
+            chain: chain.next(),
+        });
+        app.update();
+        assert_eq!(app.world.resource::<Game>().score, 0);
+    }
+
+    #[test]
+    fn idle_chain_settles_with_bullets_left() {
+        let (mut app, chain) = chain_app(2);
+        app.world.spawn((Bullet, chain.next()));
+        app.world.resource_mut::<Game>().ticks = CHAIN_IDLE_TICKS - 1;
+        app.update();
+        assert_eq!(app.world.resource::<Game>().score, 0);
+
+        app.world.resource_mut::<Game>().ticks = CHAIN_IDLE_TICKS;
+        app.update();
+        assert_eq!(app.world.resource::<Game>().score, chain_bonus(2));
+    }
+
+    #[test]
+    fn end_all_chains_adds_every_bonus() {
+        let mut game = Game::default();
+        for combo in [2, 3] {
+            let chain = game.chains.start();
+            for _ in 0..combo {
+                game.chains.add_combo(&chain, 0);
+            }
+        }
+        end_all_chains(&mut game);
+        assert_eq!(game.score, chain_bonus(2) + chain_bonus(3));
+        assert_eq!(game.chains.max_combo(), 0);
+    }
+
+    #[test]
+    fn reflect_flips_each_axis() {
+        use components::Direction::*;
+        assert_eq!(UpRight.reflect(true, false), UpLeft);
+        assert_eq!(UpRight.reflect(false, true), DownRight);
+        assert_eq!(UpRight.reflect(true, true), DownLeft);
+        assert_eq!(DownLeft.opposite(), UpRight);
+        assert_eq!(Left.reflect(false, true), Left);
+    }
+
+    #[test]
+    fn bounce_keeps_direction_when_open() {
+        let pos = Position::new(5, 5);
+        let dir = bounce(&components::Direction::UpRight, &pos, |_| false);
+        assert_eq!(dir, components::Direction::UpRight);
+    }
+
+    #[test]
+    fn bounce_reverses_cardinal_direction() {
+        let pos = Position::new(9, 5);
+        let dir = bounce(&components::Direction::Right, &pos, |p| p.x >= 10);
+        assert_eq!(dir, components::Direction::Left);
+    }
+
+    #[test]
+    fn bounce_reflects_diagonal_off_side_wall_and_ceiling() {
+        use components::Direction::*;
+        // 右の壁で横の向きだけ変わる
+        let pos = Position::new(9, 5);
+        assert_eq!(bounce(&UpRight, &pos, |p| p.x >= 10), UpLeft);
+        // 天井で縦の向きだけ変わる
+        let pos = Position::new(5, 2);
+        assert_eq!(bounce(&UpRight, &pos, |p| p.y <= 1), DownRight);
+        // 隅では来た向きに戻る
+        let pos = Position::new(9, 2);
+        assert_eq!(bounce(&UpRight, &pos, |p| p.x >= 10 || p.y <= 1), DownLeft);
+    }
+
+    #[test]
+    fn bounce_reverses_diagonal_at_wall_corner_or_when_reflection_is_blocked() {
+        use components::Direction::*;
+        // 斜め先のセルだけが壁
+        let pos = Position::new(5, 5);
+        let wall = Position::new(6, 4);
+        assert_eq!(bounce(&UpRight, &pos, |p| *p == wall), DownLeft);
+        // 跳ね返った先も壁
+        let pos = Position::new(9, 5);
+        let wall = Position::new(8, 4);
+        assert_eq!(
+            bounce(&UpRight, &pos, |p| p.x >= 10 || *p == wall),
+            DownLeft
+        );
+    }
+
+    #[test]
+    fn bullets_swapping_next_to_wall_collide() {
+        let mut app = App::new();
+        app.insert_resource(Arena::default())
+            .init_resource::<Occupancy>()
+            .add_systems(
+                Update,
+                (
+                    bullet_system,
+                    occupancy_system,
+                    collision_bullet_bullet_system,
+                )
+                    .chain(),
+            );
+        // 左の壁はx = 1。aはx = 2に入って壁で右向きに変わり、bとセルを入れ替える
+        let y = 10;
+        let a = app
+            .world
+            .spawn((
+                Bullet,
+                Position::new(3, y),
+                PreviousPosition(Position::new(3, y)),
+                components::Direction::Left,
+            ))
+            .id();
+        let b = app
+            .world
+            .spawn((
+                Bullet,
+                Position::new(2, y),
+                PreviousPosition(Position::new(2, y)),
+                components::Direction::Right,
+            ))
+            .id();
+        app.update();
+        assert!(app.world.get_entity(a).is_none());
+        assert!(app.world.get_entity(b).is_none());
+    }
+}
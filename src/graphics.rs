@@ -0,0 +1,374 @@
+use bevy::prelude::*;
+use bevy_framepace::Limiter;
+
+use crate::arena::*;
+use crate::assets::*;
+use crate::components::{self, *};
+use crate::events::*;
+use crate::gameplay::GameplaySet;
+use crate::resources::*;
+use crate::states::*;
+use crate::CELL_SIZE_PX;
+
+// 弾に当たらない間、プレイヤーを点滅させる間隔(tick)
+const PLAYER_BLINK_TICKS: u32 = 3;
+
+// 画面の上に置くtitle.pngの左上のセル
+pub const TITLE_POSITION: Position = Position { x: 1, y: 0 };
+
+// やられたときの表示。プレイヤーを出し直したら消す
+#[derive(Component)]
+struct CrashEffect;
+
+// ゲームロジックが作ったentityにスプライトを付けて描画する
+pub struct GraphicsPlugin;
+
+impl Plugin for GraphicsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(bevy_framepace::FramepacePlugin)
+            // ゲームロジックはFixedUpdateで進むので、描画はディスプレイのリフレッシュレートに合わせる
+            .insert_resource(bevy_framepace::FramepaceSettings {
+                limiter: Limiter::Auto,
+            })
+            .insert_resource(ClearColor(Color::rgb(0.0, 0.0, 0.0)))
+            .init_resource::<Textures>()
+            .add_systems(Startup, startup)
+            .add_systems(
+                OnEnter(GameState::Playing),
+                spawn_playfield.after(GameplaySet).run_if(not_resuming),
+            )
+            .add_systems(
+                OnEnter(GameState::Attract),
+                spawn_playfield.after(GameplaySet),
+            )
+            .add_systems(
+                Update,
+                (
+                    player_sprite_system,
+                    player_blink_system,
+                    bullet_sprite_system,
+                    target_sprite_system,
+                    pending_burst_sprite_system,
+                    transform_system,
+                    crash_effect_system,
+                    clear_crash_effect_system,
+                )
+                    .chain()
+                    .after(GameplaySet),
+            );
+    }
+}
+
+pub fn create_top_left_sprite() -> Sprite {
+    Sprite {
+        anchor: bevy::sprite::Anchor::TopLeft,
+        ..default()
+    }
+}
+
+// セル座標をワールド座標に変換する
+// ワールド座標のyは上向きなので、セル(0, 0)の左上を原点にしてyの符号を反転する
+pub fn position_to_transform(position: Position) -> Transform {
+    Transform::from_xyz(
+        CELL_SIZE_PX * position.x as f32,
+        -CELL_SIZE_PX * position.y as f32,
+        0.0,
+    )
+}
+
+fn bullet_image(direction: &components::Direction) -> &'static str {
+    match direction {
+        components::Direction::Up => IMAGE_UP,
+        components::Direction::Left => IMAGE_LEFT,
+        components::Direction::Down => IMAGE_DOWN,
+        components::Direction::Right => IMAGE_RIGHT,
+        components::Direction::UpLeft => IMAGE_UP_LEFT,
+        components::Direction::UpRight => IMAGE_UP_RIGHT,
+        components::Direction::DownLeft => IMAGE_DOWN_LEFT,
+        components::Direction::DownRight => IMAGE_DOWN_RIGHT,
+    }
+}
+
+fn startup(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut textures: ResMut<Textures>,
+    mut texture_atlas_layouts: ResMut<Assets<TextureAtlasLayout>>,
+    screen: Res<Screen>,
+) {
+    // Texture
+    textures.back = asset_server.load(IMAGE_BACK);
+    for direction in components::Direction::all() {
+        textures.bullets[direction.to_i32() as usize] = asset_server.load(bullet_image(&direction));
+    }
+    textures.dust = asset_server.load(IMAGE_DUST);
+    textures.font = asset_server.load(IMAGE_FONT);
+    textures.font_layout = texture_atlas_layouts.add(TextureAtlasLayout::from_grid(
+        IMAGE_FONT_TILE_SIZE,
+        IMAGE_FONT_TILE_COLUMNS,
+        IMAGE_FONT_TILE_ROWS,
+        None,
+        None,
+    ));
+    textures.numbers = asset_server.load(IMAGE_NUMBERS);
+    textures.numbers_layout = texture_atlas_layouts.add(TextureAtlasLayout::from_grid(
+        IMAGE_NUMBERS_TILE_SIZE,
+        IMAGE_NUMBERS_TILE_COLUMNS,
+        IMAGE_NUMBERS_TILE_ROWS,
+        None,
+        None,
+    ));
+    textures.player = asset_server.load(IMAGE_PLAYER);
+    textures.target = asset_server.load(IMAGE_TARGET);
+    textures.title = asset_server.load(IMAGE_TITLE);
+    textures.wall = asset_server.load(IMAGE_WALL);
+
+    // Camera
+    // 画面左上がワールド座標(0, 0)、右下が(幅, -高さ)となるようにカメラを移動
+    let projection = OrthographicProjection::default();
+    let screen_size = screen.size();
+    commands.spawn(Camera2dBundle {
+        transform: Transform::from_xyz(
+            screen_size.x / 2.0,
+            -screen_size.y / 2.0,
+            projection.far - 1.0,
+        ),
+        projection,
+        ..default()
+    });
+}
+
+fn spawn_playfield(mut commands: Commands, textures: Res<Textures>, arena: Res<Arena>) {
+    spawn_arena(&arena, &mut commands, &textures);
+
+    // Title
+    commands.spawn((
+        DespawnOnRestart,
+        SpriteBundle {
+            texture: textures.title.clone(),
+            transform: position_to_transform(TITLE_POSITION),
+            sprite: create_top_left_sprite(),
+            ..default()
+        },
+    ));
+}
+
+// 領域の壁と下の床を描画する
+// 壁と床のentityを返す
+pub fn spawn_arena(arena: &Arena, commands: &mut Commands, textures: &Textures) -> Vec<Entity> {
+    let sprite: Sprite = create_top_left_sprite();
+    let mut entities = Vec::new();
+
+    // Walls
+    let mut spawn_wall = |x, y| {
+        let entity = commands.spawn((
+            DespawnOnRestart,
+            SpriteBundle {
+                texture: textures.wall.clone(),
+                transform: position_to_transform(Position::new(x, y)),
+                sprite: sprite.clone(),
+                ..default()
+            },
+        ));
+        entities.push(entity.id());
+    };
+    for y in (arena.y_min - 1)..=arena.y_max {
+        spawn_wall(arena.x_min - 1, y);
+        spawn_wall(arena.x_max + 1, y);
+    }
+    for x in arena.x_min..=arena.x_max {
+        spawn_wall(x, arena.y_min - 1);
+    }
+    for wall in &arena.walls {
+        spawn_wall(wall.x, wall.y);
+    }
+
+    // Back
+    for i in (arena.x_min - 2)..=(arena.x_max + 2) {
+        let entity = commands.spawn((
+            DespawnOnRestart,
+            SpriteBundle {
+                texture: textures.back.clone(),
+                transform: position_to_transform(Position::new(i, arena.y_max + 1)),
+                sprite: sprite.clone(),
+                ..default()
+            },
+        ));
+        entities.push(entity.id());
+    }
+    entities
+}
+
+fn player_sprite_system(
+    mut commands: Commands,
+    query: Query<(Entity, &Position), Added<Player>>,
+    textures: Res<Textures>,
+) {
+    for (entity, position) in &query {
+        commands.entity(entity).insert(SpriteBundle {
+            texture: textures.player.clone(),
+            transform: position_to_transform(position.clone()),
+            sprite: create_top_left_sprite(),
+            ..default()
+        });
+    }
+}
+
+// 弾に当たらない間は点滅させる
+fn player_blink_system(mut query: Query<(Option<&Invulnerable>, &mut Visibility), With<Player>>) {
+    for (invulnerable, mut visibility) in &mut query {
+        let visible = invulnerable
+            .is_none_or(|invulnerable| (invulnerable.ticks / PLAYER_BLINK_TICKS).is_multiple_of(2));
+        let new_visibility = if visible {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        };
+        if *visibility != new_visibility {
+            *visibility = new_visibility;
+        }
+    }
+}
+
+fn bullet_sprite_system(
+    mut commands: Commands,
+    added_query: Query<(Entity, &Position, &components::Direction, Has<Dust>), Added<Bullet>>,
+    mut changed_query: Query<
+        (&components::Direction, &mut Handle<Image>),
+        (With<Bullet>, Without<Dust>, Changed<components::Direction>),
+    >,
+    textures: Res<Textures>,
+) {
+    for (entity, position, direction, is_dust) in &added_query {
+        commands.entity(entity).insert(SpriteBundle {
+            texture: if is_dust {
+                textures.dust.clone()
+            } else {
+                textures.bullets[direction.to_i32() as usize].clone()
+            },
+            transform: position_to_transform(position.clone()),
+            sprite: create_top_left_sprite(),
+            ..default()
+        });
+    }
+
+    // 跳ね返ったらスプライトを変える
+    for (direction, mut handle) in &mut changed_query {
+        *handle = textures.bullets[direction.to_i32() as usize].clone();
+    }
+}
+
+fn target_sprite_system(
+    mut commands: Commands,
+    added_query: Query<(Entity, &Position, &Target), Added<Target>>,
+    mut changed_query: Query<(&Target, &mut Sprite), Changed<Target>>,
+    textures: Res<Textures>,
+) {
+    for (entity, position, target) in &added_query {
+        commands.entity(entity).insert(SpriteBundle {
+            texture: textures.target.clone(),
+            transform: position_to_transform(position.clone()),
+            sprite: Sprite {
+                color: target.color(),
+                ..create_top_left_sprite()
+            },
+            ..default()
+        });
+    }
+
+    // 弾が当たってHPが減ったら色を変える
+    for (target, mut sprite) in &mut changed_query {
+        sprite.color = target.color();
+    }
+}
+
+// 時間差で破裂するところには、薄いターゲットを出しておく
+fn pending_burst_sprite_system(
+    mut commands: Commands,
+    query: Query<(Entity, &Position), Added<PendingBurst>>,
+    textures: Res<Textures>,
+) {
+    for (entity, position) in &query {
+        commands.entity(entity).insert(SpriteBundle {
+            texture: textures.target.clone(),
+            transform: position_to_transform(position.clone()),
+            sprite: Sprite {
+                color: Color::rgba(1.0, 1.0, 1.0, 0.4),
+                ..create_top_left_sprite()
+            },
+            ..default()
+        });
+    }
+}
+
+// 前のtickの位置と今の位置の間を、次のtickまでの経過時間で補間して滑らかに動かす
+// ゲームが止まっているときは今の位置に置く
+fn transform_system(
+    mut query: Query<(&Position, Option<&PreviousPosition>, &mut Transform)>,
+    fixed_time: Res<Time<Fixed>>,
+    state: Res<State<GameState>>,
+) {
+    let alpha = if in_game(state) {
+        fixed_time.overstep_fraction()
+    } else {
+        1.0
+    };
+    for (position, previous, mut transform) in &mut query {
+        let current = position_to_transform(position.clone()).translation;
+        let previous = previous.map_or(current, |previous| {
+            position_to_transform(previous.0.clone()).translation
+        });
+        transform.translation = previous.lerp(current, alpha);
+    }
+}
+
+fn crash_effect_system(
+    mut commands: Commands,
+    mut crash_events: EventReader<CrashEvent>,
+    textures: Res<Textures>,
+    screen: Res<Screen>,
+) {
+    if let Some(event) = crash_events.read().next() {
+        let position = event.pos.clone();
+        for i in 0..PLAYER_WIDTH {
+            commands.spawn((
+                CrashEffect,
+                DespawnOnRestart,
+                SpriteBundle {
+                    texture: textures.dust.clone(),
+                    sprite: create_top_left_sprite(),
+                    transform: position_to_transform(Position::new(position.x + i, position.y)),
+                    ..default()
+                },
+            ));
+        }
+        commands.spawn((
+            CrashEffect,
+            DespawnOnRestart,
+            SpriteBundle {
+                sprite: Sprite {
+                    color: Color::rgba(1.0, 0.0, 0.0, 0.5),
+                    anchor: bevy::sprite::Anchor::TopLeft,
+                    custom_size: Some(screen.size()),
+                    ..default()
+                },
+                transform: Transform::from_xyz(0.0, 0.0, 3.0),
+                ..default()
+            },
+        ));
+    }
+    crash_events.clear();
+}
+
+fn clear_crash_effect_system(
+    mut commands: Commands,
+    player_query: Query<(), Added<Player>>,
+    effect_query: Query<Entity, With<CrashEffect>>,
+) {
+    if player_query.is_empty() {
+        return;
+    }
+    for entity in &effect_query {
+        commands.entity(entity).despawn();
+    }
+}
@@ -0,0 +1,146 @@
+use bevy::app::AppExit;
+use bevy::prelude::*;
+use bevy::time::TimeUpdateStrategy;
+
+use crate::gameplay::GameplaySet;
+use crate::resources::*;
+use crate::states::*;
+
+// headless時、プレイヤーが何もしなくてもゲームが終わるようにするための上限
+pub const HEADLESS_MAX_TICKS: u32 = 10000;
+
+// ウィンドウ・描画・音なしでゲームを指定回数繰り返す
+// MinimalPluginsとU235Plugin { headless: true }と一緒に使う
+pub struct HeadlessPlugin {
+    pub games: u32,
+    pub max_ticks: u32,
+}
+
+impl Default for HeadlessPlugin {
+    fn default() -> Self {
+        Self {
+            games: 1,
+            max_ticks: HEADLESS_MAX_TICKS,
+        }
+    }
+}
+
+impl Plugin for HeadlessPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ButtonInput<KeyCode>>()
+            .insert_resource(Headless {
+                games: self.games,
+                max_ticks: self.max_ticks,
+                played: 0,
+            })
+            .add_systems(Startup, headless_time)
+            .add_systems(
+                FixedUpdate,
+                headless_timeout_system
+                    .after(GameplaySet)
+                    .run_if(in_state(GameState::Playing)),
+            )
+            .add_systems(OnEnter(GameState::GameOver), headless_game_over);
+    }
+}
+
+// 実際の時間ではなく、1回の更新でちょうど1tick進める
+fn headless_time(mut commands: Commands, fixed_time: Res<Time<Fixed>>) {
+    commands.insert_resource(TimeUpdateStrategy::ManualDuration(fixed_time.timestep()));
+}
+
+fn headless_timeout_system(
+    game: Res<Game>,
+    headless: Res<Headless>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if game.ticks >= headless.max_ticks {
+        next_state.set(GameState::GameOver);
+    }
+}
+
+fn headless_game_over(
+    game: Res<Game>,
+    mut headless: ResMut<Headless>,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut app_exit_events: EventWriter<AppExit>,
+) {
+    headless.played += 1;
+    println!(
+        "game {} seed {} score {} ticks {}",
+        headless.played, game.seed, game.score, game.ticks
+    );
+    if headless.played >= headless.games {
+        app_exit_events.send(AppExit);
+    } else {
+        next_state.set(GameState::Playing);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::level::Levels;
+    use crate::replay::*;
+    use crate::U235Plugin;
+
+    const MAX_TICKS: u32 = 900;
+
+    // 1ゲームをheadlessで遊び、最後のスコアとtick数を返す
+    fn play(playback: Replay, record: Option<PathBuf>) -> (i32, u32) {
+        let mut app = App::new();
+        app.add_plugins((
+            MinimalPlugins,
+            U235Plugin {
+                headless: true,
+                ..default()
+            },
+            HeadlessPlugin {
+                games: 1,
+                max_ticks: MAX_TICKS,
+            },
+            ReplayPlugin {
+                record,
+                playback: Some(playback),
+            },
+        ));
+        app.finish();
+        app.cleanup();
+        for _ in 0..MAX_TICKS * 2 {
+            app.update();
+            if !app.world.resource::<Events<AppExit>>().is_empty() {
+                let game = app.world.resource::<Game>();
+                return (game.score, game.ticks);
+            }
+        }
+        panic!("the game did not end");
+    }
+
+    // 左右に動きながら撃ち続ける
+    fn scripted_replay() -> Replay {
+        let mut replay = Replay::new(42, LivesRule::default(), &Levels::default());
+        replay.intents = (0..MAX_TICKS)
+            .map(|tick| PlayerIntent {
+                left: tick / 60 % 2 == 0,
+                right: tick / 60 % 2 == 1,
+                fire: tick % 4 == 0,
+                switch_weapon: false,
+            })
+            .collect();
+        replay
+    }
+
+    #[test]
+    fn recorded_game_replays_to_same_score() {
+        let path = std::env::temp_dir().join(format!("u235-headless-{}.u235", std::process::id()));
+        let (score, ticks) = play(scripted_replay(), Some(path.clone()));
+        let recorded = Replay::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert!(score > 0);
+        assert_eq!(recorded.intents.len(), ticks as usize);
+        assert_eq!(play(recorded, None), (score, ticks));
+    }
+}
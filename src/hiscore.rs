@@ -0,0 +1,305 @@
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::arena::*;
+use crate::font::BitmapText;
+use crate::hud::{spawn_bitmap_text, spawn_panel, text_transform};
+use crate::name_entry::NameEntryPlugin;
+use crate::resources::*;
+use crate::ron_file::*;
+use crate::states::*;
+
+pub const HISCORE_TABLE_SIZE: usize = 10;
+const HISCORE_FILE_NAME: &str = "hiscores.ron";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HiScoreEntry {
+    pub name: String,
+    pub score: i32,
+    // UNIX時間(秒)
+    pub date: u64,
+    pub seed: u64,
+    // ゲームの長さ(tick数)
+    pub ticks: u32,
+}
+
+// スコアの高い順に並んだ上位HISCORE_TABLE_SIZE件
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct HiScoreTable {
+    pub entries: Vec<HiScoreEntry>,
+}
+
+impl HiScoreTable {
+    // ファイルがなければ空の表を返す
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let mut table: Self = load_ron_or_default(path)?;
+        table
+            .entries
+            .sort_by_key(|entry| std::cmp::Reverse(entry.score));
+        table.entries.truncate(HISCORE_TABLE_SIZE);
+        Ok(table)
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        save_ron(path, self)
+    }
+
+    pub fn best_score(&self) -> i32 {
+        self.entries.first().map_or(0, |entry| entry.score)
+    }
+
+    pub fn qualifies(&self, score: i32) -> bool {
+        score > 0
+            && (self.entries.len() < HISCORE_TABLE_SIZE
+                || self.entries.last().is_some_and(|entry| score > entry.score))
+    }
+
+    // 表に入れば順位(0始まり)を返す
+    // 同点の場合は先に登録されたほうが上
+    pub fn insert(&mut self, entry: HiScoreEntry) -> Option<usize> {
+        if !self.qualifies(entry.score) {
+            return None;
+        }
+        let rank = self
+            .entries
+            .iter()
+            .position(|e| e.score < entry.score)
+            .unwrap_or(self.entries.len());
+        self.entries.insert(rank, entry);
+        self.entries.truncate(HISCORE_TABLE_SIZE);
+        Some(rank)
+    }
+}
+
+#[derive(Resource)]
+pub struct HiScores {
+    // Noneなら保存しない
+    pub path: Option<PathBuf>,
+    pub table: HiScoreTable,
+    // 直前のゲームが入った順位
+    pub last_rank: Option<usize>,
+    // 名前入力中ならtrue
+    pub entering_name: bool,
+}
+
+impl HiScores {
+    // 表に登録して保存する
+    pub fn record(&mut self, name: String, game: &Game) {
+        self.last_rank = self.table.insert(HiScoreEntry {
+            name,
+            score: game.score,
+            date: now_unix_secs(),
+            seed: game.seed,
+            ticks: game.ticks,
+        });
+        if self.last_rank.is_none() {
+            return;
+        }
+        if let Some(path) = &self.path {
+            if let Err(e) = self.table.save(path) {
+                warn!("failed to save hi-scores {}: {}", path.display(), e);
+            }
+        }
+    }
+}
+
+// ユーザーのデータディレクトリに置くハイスコア表のパス
+pub fn default_hiscore_path() -> Option<PathBuf> {
+    dirs::data_dir().map(|dir| dir.join("u235").join(HISCORE_FILE_NAME))
+}
+
+pub fn now_unix_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs())
+}
+
+// ハイスコア表の読み込み・更新・保存と結果画面
+// 表に入るスコアならゲームオーバーの後に名前入力画面を出す
+pub struct HiScorePlugin {
+    pub path: Option<PathBuf>,
+}
+
+impl Default for HiScorePlugin {
+    fn default() -> Self {
+        Self {
+            path: default_hiscore_path(),
+        }
+    }
+}
+
+impl Plugin for HiScorePlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(HiScores {
+            path: self.path.clone(),
+            table: HiScoreTable::default(),
+            last_rank: None,
+            entering_name: false,
+        })
+        .add_plugins(NameEntryPlugin)
+        .add_systems(Startup, load_hiscores)
+        .add_systems(
+            OnTransition {
+                from: GameState::Playing,
+                to: GameState::GameOver,
+            },
+            check_hiscore.run_if(not_playtesting),
+        )
+        // 最後の面をクリアしたとき
+        .add_systems(
+            OnTransition {
+                from: GameState::LevelClear,
+                to: GameState::GameOver,
+            },
+            check_hiscore.run_if(not_playtesting),
+        )
+        .add_systems(
+            OnEnter(GameState::GameOver),
+            spawn_results.run_if(|hiscores: Res<HiScores>| !hiscores.entering_name),
+        );
+    }
+}
+
+fn load_hiscores(mut hiscores: ResMut<HiScores>, mut game: ResMut<Game>) {
+    if let Some(path) = hiscores.path.clone() {
+        match HiScoreTable::load(&path) {
+            Ok(table) => hiscores.table = table,
+            Err(e) => warn!("failed to load hi-scores {}: {}", path.display(), e),
+        }
+    }
+    game.hi_score = game.hi_score.max(hiscores.table.best_score());
+}
+
+fn check_hiscore(
+    mut hiscores: ResMut<HiScores>,
+    game: Res<Game>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    hiscores.last_rank = None;
+    if hiscores.table.qualifies(game.score) {
+        hiscores.entering_name = true;
+        next_state.set(GameState::NameEntry);
+    }
+}
+
+// 結果画面
+// 1行に順位・名前・スコア・日付(YYYYMMDD)・ゲーム時間(秒)を表示し、今回の記録は黄色にする
+fn spawn_results(
+    mut commands: Commands,
+    hiscores: Res<HiScores>,
+    fixed_time: Res<Time<Fixed>>,
+    screen: Res<Screen>,
+) {
+    const TOP: i32 = 5;
+    spawn_panel(
+        &screen,
+        TOP - 1,
+        HISCORE_TABLE_SIZE as i32 + 2,
+        &mut commands,
+    );
+
+    let rows: Vec<String> = hiscores
+        .table
+        .entries
+        .iter()
+        .enumerate()
+        .map(|(i, entry)| {
+            format!(
+                "{:2}  {:3}  {:8}  {}  {:5}",
+                i + 1,
+                entry.name,
+                entry.score,
+                format_date(entry.date),
+                (entry.ticks as f64 * fixed_time.timestep().as_secs_f64()) as u32
+            )
+        })
+        .collect();
+    // 各行の左端を揃えて、一番長い行を画面の中央に置く
+    let width = rows
+        .iter()
+        .map(|row| BitmapText::new(row.as_str(), Color::WHITE).width())
+        .fold(0.0, f32::max);
+    for (i, row) in rows.iter().enumerate() {
+        let color = if hiscores.last_rank == Some(i) {
+            Color::YELLOW
+        } else {
+            Color::WHITE
+        };
+        let mut row_pos = text_transform(0, TOP + i as i32);
+        row_pos.translation.x = ((screen.size().x - width) / 2.0).floor();
+        spawn_bitmap_text(BitmapText::new(row.as_str(), color), row_pos, &mut commands);
+    }
+}
+
+// UNIX時間をYYYYMMDD(UTC)にする
+fn format_date(unix_secs: u64) -> String {
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = (unix_secs / 86400) as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{:04}{:02}{:02}", year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(name: &str, score: i32) -> HiScoreEntry {
+        HiScoreEntry {
+            name: name.to_string(),
+            score,
+            date: 0,
+            seed: 0,
+            ticks: 0,
+        }
+    }
+
+    fn scores(table: &HiScoreTable) -> Vec<i32> {
+        table.entries.iter().map(|entry| entry.score).collect()
+    }
+
+    #[test]
+    fn insert_keeps_order() {
+        let mut table = HiScoreTable::default();
+        assert_eq!(table.insert(entry("AAA", 100)), Some(0));
+        assert_eq!(table.insert(entry("BBB", 300)), Some(0));
+        assert_eq!(table.insert(entry("CCC", 200)), Some(1));
+        // 同点なら先に登録されたほうが上
+        assert_eq!(table.insert(entry("DDD", 200)), Some(2));
+        assert_eq!(scores(&table), [300, 200, 200, 100]);
+        assert_eq!(table.entries[1].name, "CCC");
+        assert_eq!(table.best_score(), 300);
+    }
+
+    #[test]
+    fn insert_rejects_zero_score() {
+        let mut table = HiScoreTable::default();
+        assert_eq!(table.insert(entry("AAA", 0)), None);
+        assert!(table.entries.is_empty());
+    }
+
+    #[test]
+    fn insert_truncates_to_table_size() {
+        let mut table = HiScoreTable::default();
+        for i in 1..=HISCORE_TABLE_SIZE as i32 {
+            table.insert(entry("AAA", i * 10));
+        }
+        // 最下位と同点では入らない
+        assert_eq!(table.insert(entry("BBB", 10)), None);
+        assert_eq!(table.insert(entry("CCC", 15)), Some(HISCORE_TABLE_SIZE - 1));
+        assert_eq!(table.insert(entry("DDD", 1000)), Some(0));
+        assert_eq!(table.entries.len(), HISCORE_TABLE_SIZE);
+        assert_eq!(table.entries[0].name, "DDD");
+        assert_eq!(table.entries.last().unwrap().score, 20);
+    }
+}
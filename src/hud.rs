@@ -0,0 +1,530 @@
+use bevy::prelude::*;
+
+use crate::arena::*;
+use crate::assets::*;
+use crate::components::*;
+use crate::events::*;
+use crate::font::*;
+use crate::gameplay::GameplaySet;
+use crate::graphics::{position_to_transform, TITLE_POSITION};
+use crate::level::*;
+use crate::resources::*;
+use crate::states::*;
+use crate::CELL_SIZE_PX;
+
+#[derive(Component)]
+struct WeaponLabel(WeaponKind);
+
+#[derive(Component)]
+struct ComboLabel;
+
+// このコンボ数から表示する
+const MIN_COMBO_SHOWN: u32 = 2;
+
+// ターゲットを壊したところに出す得点の表示。浮き上がりながら消えていく
+#[derive(Component)]
+struct ScorePopup {
+    timer: Timer,
+    // 出したときのワールド座標のy
+    y: f32,
+}
+
+const SCORE_POPUP_SECS: f32 = 0.8;
+const SCORE_POPUP_RISE_PX: f32 = CELL_SIZE_PX;
+
+// 値が変わったときだけ書き換える数の表示
+// BitmapTextと一緒に付け、文字列の揃え方と色はBitmapTextで決める
+#[derive(Component, Debug, Clone)]
+pub struct NumberDisplay {
+    pub source: NumberSource,
+    // この桁数に満たなければ左を埋める
+    pub width: usize,
+    // trueなら0で、falseなら空白で埋める
+    pub zero_pad: bool,
+    // 表示している値
+    value: Option<i64>,
+}
+
+// 表示する値
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NumberSource {
+    Score,
+    HiScore,
+    Lives,
+    // 続いている連鎖の一番大きいコンボ数
+    Combo,
+    // 今の面を始めてからの秒数
+    Timer,
+    // 今の面の番号。1から数える
+    Wave,
+}
+
+impl NumberDisplay {
+    pub fn new(source: NumberSource) -> Self {
+        NumberDisplay {
+            source,
+            width: 0,
+            zero_pad: false,
+            value: None,
+        }
+    }
+
+    pub fn width(mut self, width: usize) -> Self {
+        self.width = width;
+        self
+    }
+
+    pub fn zero_padded(mut self) -> Self {
+        self.zero_pad = true;
+        self
+    }
+
+    pub fn format(&self, value: i64) -> String {
+        if self.zero_pad {
+            format!("{:0width$}", value, width = self.width)
+        } else {
+            format!("{:width$}", value, width = self.width)
+        }
+    }
+}
+
+impl NumberSource {
+    fn value(&self, game: &Game, fixed_time: &Time<Fixed>) -> i64 {
+        match self {
+            NumberSource::Score => game.score as i64,
+            NumberSource::HiScore => game.hi_score as i64,
+            NumberSource::Lives => game.lives as i64,
+            NumberSource::Combo => game.chains.max_combo() as i64,
+            NumberSource::Timer => {
+                (game.level_ticks as f64 * fixed_time.timestep().as_secs_f64()) as i64
+            }
+            NumberSource::Wave => game.level as i64 + 1,
+        }
+    }
+}
+
+const SCORE_WIDTH: usize = 8;
+// title.pngのHiScore・Scoreのラベルの左端の、画像の左端からのセル数
+const HISCORE_LABEL_CELL: i32 = 21;
+const SCORE_LABEL_CELL: i32 = 35;
+const LIVES_LABEL: &str = "LIVES ";
+const STAGE_LABEL: &str = "STAGE ";
+const COMBO_LABEL: &str = "COMBO ";
+const TIMER_LABEL: &str = "TIME ";
+const STAGE_CLEAR_TEXT: &str = "STAGE CLEAR";
+const ALL_CLEAR_TEXT: &str = "ALL STAGES CLEAR";
+const WEAPON_LABEL_PREFIX: &str = "WEAPON ";
+
+// スコア・ハイスコア・武器・残機・コンボ数・経過時間・面・得点の表示
+pub struct HudPlugin;
+
+impl Plugin for HudPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            OnEnter(GameState::Playing),
+            spawn_hud.after(GameplaySet).run_if(not_resuming),
+        )
+        .add_systems(OnEnter(GameState::Attract), spawn_hud.after(GameplaySet))
+        .add_systems(OnEnter(GameState::LevelClear), spawn_level_clear)
+        .add_systems(
+            Update,
+            (
+                weapon_label_system,
+                combo_label_system,
+                spawn_score_popup_system,
+                score_popup_system,
+            )
+                .after(GameplaySet)
+                .run_if(in_game),
+        )
+        // 面をクリアしたときのボーナスも反映するため、ゲーム中でなくても動かす
+        .add_systems(Update, number_display_system.after(GameplaySet));
+    }
+}
+
+fn spawn_hud(mut commands: Commands, game: Res<Game>, arena: Res<Arena>, levels: Res<Levels>) {
+    // Score, HiScore。8桁でtitle.pngのラベルの左に右揃えで置く
+    for (source, label_cell) in [
+        (NumberSource::HiScore, HISCORE_LABEL_CELL),
+        (NumberSource::Score, SCORE_LABEL_CELL),
+    ] {
+        let cx = TITLE_POSITION.x + label_cell;
+        spawn_number_display(
+            NumberDisplay::new(source).width(SCORE_WIDTH),
+            TextAlign::Right,
+            text_transform(cx, 0),
+            Color::WHITE,
+            &mut commands,
+        );
+    }
+
+    // 残機は武器の表示の右に置く
+    let cx = arena.x_min + lives_label_offset();
+    spawn_text(
+        LIVES_LABEL,
+        cx,
+        arena.y_max + 2,
+        Color::WHITE,
+        &mut commands,
+    );
+    spawn_number_display(
+        NumberDisplay::new(NumberSource::Lives),
+        TextAlign::Left,
+        text_transform(cx + LIVES_LABEL.len() as i32 / 2, arena.y_max + 2),
+        Color::WHITE,
+        &mut commands,
+    );
+
+    // 面が2つ以上あるときは、今の面を領域の右下に右揃えで表示する
+    // 番号は一番大きい面の番号の桁数に揃える
+    if levels.levels.len() > 1 {
+        let width = levels.levels.len().to_string().len();
+        let number_pos = text_transform(arena.x_max + 1, arena.y_max + 2);
+        let mut label_pos = number_pos;
+        label_pos.translation.x -= IMAGE_FONT_TILE_SIZE.x * width as f32;
+        spawn_bitmap_text(
+            BitmapText::new(STAGE_LABEL, Color::WHITE).aligned(TextAlign::Right),
+            label_pos,
+            &mut commands,
+        );
+        spawn_number_display(
+            NumberDisplay::new(NumberSource::Wave).width(width),
+            TextAlign::Right,
+            number_pos,
+            Color::WHITE,
+            &mut commands,
+        );
+    }
+
+    // 生き残るとクリアする面では、経過時間を領域の下の真ん中に置く
+    if let WinCondition::Survive(_) = levels.get(game.level).win {
+        let number_pos = text_transform_centered(&arena, arena.y_max + 2);
+        spawn_bitmap_text(
+            BitmapText::new(TIMER_LABEL, Color::WHITE).aligned(TextAlign::Right),
+            number_pos,
+            &mut commands,
+        );
+        spawn_number_display(
+            NumberDisplay::new(NumberSource::Timer),
+            TextAlign::Left,
+            number_pos,
+            Color::WHITE,
+            &mut commands,
+        );
+    }
+
+    // コンボ数は領域の上の真ん中に置き、combo_label_systemで表示を切り替える
+    let number_pos = text_transform_centered(&arena, arena.y_min);
+    let combo_label = spawn_bitmap_text(
+        BitmapText::new(COMBO_LABEL, Color::YELLOW).aligned(TextAlign::Right),
+        number_pos,
+        &mut commands,
+    );
+    let combo_number = spawn_number_display(
+        NumberDisplay::new(NumberSource::Combo),
+        TextAlign::Left,
+        number_pos,
+        Color::YELLOW,
+        &mut commands,
+    );
+    for entity in [combo_label, combo_number] {
+        commands
+            .entity(entity)
+            .insert((ComboLabel, Visibility::Hidden));
+    }
+}
+
+fn spawn_level_clear(
+    mut commands: Commands,
+    game: Res<Game>,
+    arena: Res<Arena>,
+    levels: Res<Levels>,
+) {
+    let text = if levels.is_last(game.level) {
+        ALL_CLEAR_TEXT
+    } else {
+        STAGE_CLEAR_TEXT
+    };
+    // 領域の中央に置く
+    let mut text_pos = position_to_transform(Position::new(0, (arena.y_min + arena.y_max) / 2));
+    text_pos.translation.x = arena_center_x(&arena);
+    text_pos.translation.z = 5.0;
+    spawn_bitmap_text(
+        BitmapText::new(text, Color::YELLOW).aligned(TextAlign::Center),
+        text_pos,
+        &mut commands,
+    );
+}
+
+// transformの位置に揃えて数を表示する
+pub fn spawn_number_display(
+    display: NumberDisplay,
+    align: TextAlign,
+    transform: Transform,
+    color: Color,
+    commands: &mut Commands,
+) -> Entity {
+    let entity = spawn_bitmap_text(
+        BitmapText::new("", color).aligned(align),
+        transform,
+        commands,
+    );
+    commands.entity(entity).insert(display);
+    entity
+}
+
+// (cx, cy)のセルの左上に文字を置くTransform。ゲームの表示より手前にする
+pub fn text_transform(cx: i32, cy: i32) -> Transform {
+    let mut transform = position_to_transform(Position::new(cx, cy));
+    transform.translation.z = 5.0;
+    transform
+}
+
+// (cx, cy)のセルの左上から文字列を描画する
+pub fn spawn_text(text: &str, cx: i32, cy: i32, color: Color, commands: &mut Commands) -> Entity {
+    spawn_bitmap_text(
+        BitmapText::new(text, color),
+        text_transform(cx, cy),
+        commands,
+    )
+}
+
+// 文字列を画面の中央に揃えて、cyの行に描画する
+pub fn spawn_centered_text(
+    text: &str,
+    cy: i32,
+    color: Color,
+    screen: &Screen,
+    commands: &mut Commands,
+) -> Entity {
+    let mut text_pos = position_to_transform(Position::new(0, cy));
+    text_pos.translation.x = screen.size().x / 2.0;
+    text_pos.translation.z = 5.0;
+    spawn_bitmap_text(
+        BitmapText::new(text, color).aligned(TextAlign::Center),
+        text_pos,
+        commands,
+    )
+}
+
+pub fn spawn_bitmap_text(
+    text: BitmapText,
+    transform: Transform,
+    commands: &mut Commands,
+) -> Entity {
+    commands
+        .spawn((
+            text,
+            DespawnOnRestart,
+            SpatialBundle::from_transform(transform),
+        ))
+        .id()
+}
+
+// 画面幅いっぱいの半透明の黒い板。ゲームオーバーの赤い画面より手前に置く
+pub fn spawn_panel(screen: &Screen, top: i32, rows: i32, commands: &mut Commands) -> Entity {
+    let mut panel_pos = position_to_transform(Position::new(0, top));
+    panel_pos.translation.z = 4.0;
+    commands
+        .spawn((
+            DespawnOnRestart,
+            SpriteBundle {
+                sprite: Sprite {
+                    color: Color::rgba(0.0, 0.0, 0.0, 0.8),
+                    anchor: bevy::sprite::Anchor::TopLeft,
+                    custom_size: Some(Vec2::new(screen.size().x, CELL_SIZE_PX * rows as f32)),
+                    ..default()
+                },
+                transform: panel_pos,
+                ..default()
+            },
+        ))
+        .id()
+}
+
+fn number_display_system(
+    mut query: Query<(&mut NumberDisplay, &mut BitmapText)>,
+    game: Res<Game>,
+    fixed_time: Res<Time<Fixed>>,
+) {
+    for (mut display, mut text) in &mut query {
+        let value = display.source.value(&game, &fixed_time);
+        if display.value != Some(value) {
+            display.value = Some(value);
+            text.text = display.format(value);
+        }
+    }
+}
+
+// 続いている連鎖のコンボ数が大きくなったときだけ表示する
+fn combo_label_system(mut label_query: Query<&mut Visibility, With<ComboLabel>>, game: Res<Game>) {
+    let visibility = if game.chains.max_combo() >= MIN_COMBO_SHOWN {
+        Visibility::Inherited
+    } else {
+        Visibility::Hidden
+    };
+    for mut label_visibility in &mut label_query {
+        label_visibility.set_if_neq(visibility);
+    }
+}
+
+// 得点をターゲットのセルの中央に揃えて出す。倍率が付いていれば黄色で倍率も出す
+// 領域からははみ出さないようにし、領域より幅が広ければ領域の中央に置く
+fn spawn_score_popup_system(
+    mut commands: Commands,
+    mut score_events: EventReader<ScoreEvent>,
+    arena: Res<Arena>,
+) {
+    for event in score_events.read() {
+        let text = if event.multiplier > 1 {
+            BitmapText::new(
+                format!("{} X{}", event.points, event.multiplier),
+                Color::YELLOW,
+            )
+        } else {
+            BitmapText::new(event.points.to_string(), Color::WHITE)
+        };
+        let half_width = (text.width() / 2.0).floor();
+        let min_x = CELL_SIZE_PX * arena.x_min as f32 + half_width;
+        let max_x = CELL_SIZE_PX * (arena.x_max + 1) as f32 - (text.width() - half_width);
+        let mut text_pos = position_to_transform(event.pos.clone());
+        text_pos.translation.x = if min_x <= max_x {
+            (text_pos.translation.x + CELL_SIZE_PX / 2.0).clamp(min_x, max_x)
+        } else {
+            arena_center_x(&arena)
+        };
+        text_pos.translation.z = 5.0;
+        let entity = spawn_bitmap_text(text.aligned(TextAlign::Center), text_pos, &mut commands);
+        commands.entity(entity).insert(ScorePopup {
+            timer: Timer::from_seconds(SCORE_POPUP_SECS, TimerMode::Once),
+            y: text_pos.translation.y,
+        });
+    }
+}
+
+fn score_popup_system(
+    mut commands: Commands,
+    mut query: Query<(Entity, &mut ScorePopup, &mut Transform, &mut BitmapText)>,
+    time: Res<Time>,
+) {
+    for (entity, mut popup, mut transform, mut text) in &mut query {
+        popup.timer.tick(time.delta());
+        if popup.timer.finished() {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        }
+        let fraction = popup.timer.fraction();
+        transform.translation.y = popup.y + SCORE_POPUP_RISE_PX * fraction;
+        text.color.set_a(1.0 - fraction);
+    }
+}
+
+// 一番長い武器の表示と1セル空ける
+fn lives_label_offset() -> i32 {
+    let longest = WeaponKind::all()
+        .iter()
+        .map(|kind| WEAPON_LABEL_PREFIX.len() + kind.label().len())
+        .max()
+        .unwrap_or(0) as i32;
+    (longest + 1) / 2 + 1
+}
+
+// 領域の中央のワールド座標のx
+fn arena_center_x(arena: &Arena) -> f32 {
+    CELL_SIZE_PX * (arena.x_min + arena.x_max + 1) as f32 / 2.0
+}
+
+// cyの行の、領域の中央に文字を置くTransform
+fn text_transform_centered(arena: &Arena, cy: i32) -> Transform {
+    let mut transform = text_transform(0, cy);
+    transform.translation.x = arena_center_x(arena);
+    transform
+}
+
+// 武器が変わったら表示を書き換える
+fn weapon_label_system(
+    mut commands: Commands,
+    player_query: Query<&Weapon, With<Player>>,
+    mut label_query: Query<(&mut WeaponLabel, &mut BitmapText)>,
+    arena: Res<Arena>,
+) {
+    let Ok(weapon) = player_query.get_single() else {
+        return;
+    };
+    let text = format!("{}{}", WEAPON_LABEL_PREFIX, weapon.kind.label());
+    if let Ok((mut label, mut label_text)) = label_query.get_single_mut() {
+        if label.0 != weapon.kind {
+            label.0 = weapon.kind;
+            label_text.text = text;
+        }
+        return;
+    }
+
+    let entity = spawn_text(
+        &text,
+        arena.x_min,
+        arena.y_max + 2,
+        Color::WHITE,
+        &mut commands,
+    );
+    commands.entity(entity).insert(WeaponLabel(weapon.kind));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_pads_to_width() {
+        let display = NumberDisplay::new(NumberSource::Score);
+        assert_eq!(display.format(1234), "1234");
+        let display = display.width(6);
+        assert_eq!(display.format(1234), "  1234");
+        assert_eq!(display.zero_padded().format(1234), "001234");
+    }
+
+    #[test]
+    fn format_does_not_cut_long_values() {
+        let display = NumberDisplay::new(NumberSource::Score)
+            .width(3)
+            .zero_padded();
+        assert_eq!(display.format(123456), "123456");
+        assert_eq!(display.format(0), "000");
+    }
+
+    #[test]
+    fn display_follows_game_values() {
+        let mut app = App::new();
+        app.insert_resource(Game::default())
+            .insert_resource(Time::<Fixed>::from_hz(10.0))
+            .add_systems(Update, number_display_system);
+        let score = app
+            .world
+            .spawn((
+                NumberDisplay::new(NumberSource::Score).width(SCORE_WIDTH),
+                BitmapText::new("", Color::WHITE),
+            ))
+            .id();
+        let timer = app
+            .world
+            .spawn((
+                NumberDisplay::new(NumberSource::Timer)
+                    .width(3)
+                    .zero_padded(),
+                BitmapText::new("", Color::WHITE),
+            ))
+            .id();
+        let text = |app: &App, entity| app.world.get::<BitmapText>(entity).unwrap().text.clone();
+
+        app.update();
+        assert_eq!(text(&app, score), "       0");
+        assert_eq!(text(&app, timer), "000");
+
+        let mut game = app.world.resource_mut::<Game>();
+        game.score = 2500;
+        game.level_ticks = 125;
+        app.update();
+        assert_eq!(text(&app, score), "    2500");
+        assert_eq!(text(&app, timer), "012");
+    }
+}
@@ -0,0 +1,311 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::arena::*;
+use crate::components::{Position, TargetKind};
+use crate::ron_file::*;
+
+const LEVEL_EXTENSION: &str = "ron";
+// 面のファイルで定義しなくても使えるターゲットの種類。1発で壊れる
+pub const DEFAULT_TARGET_KIND: &str = "normal";
+
+fn default_target_kind() -> String {
+    DEFAULT_TARGET_KIND.into()
+}
+
+// ターゲットがランダムに出現する規則
+// 毎tick chanceの確率で、ターゲットがmax_targets個未満なら1つ出す
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SpawnRule {
+    pub chance: f64,
+    pub max_targets: usize,
+    // 出すターゲットの種類と重み。空ならnormalだけ
+    #[serde(default)]
+    pub kinds: Vec<(String, u32)>,
+}
+
+impl Default for SpawnRule {
+    fn default() -> Self {
+        Self {
+            chance: 0.07,
+            max_targets: 80,
+            kinds: Vec::new(),
+        }
+    }
+}
+
+// 面をクリアする条件
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WinCondition {
+    // クリアしない
+    #[default]
+    Endless,
+    // ターゲットを全部壊す
+    ClearTargets,
+    // その面で取ったスコアがこれ以上になる
+    Score(i32),
+    // その面でこのtick数だけ生き残る
+    Survive(u32),
+}
+
+// 最初から置かれているターゲット
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlacedTarget {
+    pub x: i32,
+    pub y: i32,
+    #[serde(default = "default_target_kind")]
+    pub kind: String,
+}
+
+impl PlacedTarget {
+    pub fn new(position: Position, kind: &str) -> Self {
+        Self {
+            x: position.x,
+            y: position.y,
+            kind: kind.into(),
+        }
+    }
+
+    pub fn position(&self) -> Position {
+        Position::new(self.x, self.y)
+    }
+}
+
+// 1つの面。領域、最初から置かれているターゲット、ランダムな出現の規則、クリアの条件
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Level {
+    pub name: String,
+    pub arena: Arena,
+    pub targets: Vec<PlacedTarget>,
+    pub spawn: SpawnRule,
+    pub win: WinCondition,
+    // この面で使うターゲットの種類
+    pub kinds: BTreeMap<String, TargetKind>,
+}
+
+impl Level {
+    // ランダムに出てくるターゲットを終わりなく撃つ面
+    pub fn endless(arena: Arena) -> Self {
+        Self { arena, ..default() }
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let mut level: Self = load_ron(path)?;
+        level.arena = level.arena.with_walls();
+        level
+            .validate()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        Ok(level)
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        save_ron(path, self)
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        self.arena.validate()?;
+        if let Some(target) = self
+            .targets
+            .iter()
+            .find(|t| !self.arena.is_open(&t.position()))
+        {
+            return Err(format!(
+                "target ({}, {}) is outside the arena or on a wall",
+                target.x, target.y
+            ));
+        }
+        if !(0.0..=1.0).contains(&self.spawn.chance) {
+            return Err("spawn chance must be between 0 and 1".into());
+        }
+        if let Some((name, _)) = self.kinds.iter().find(|(_, kind)| kind.hp == 0) {
+            return Err(format!("target kind {} must have at least 1 hp", name));
+        }
+        if let Some((name, _)) = self.kinds.iter().find(|(_, kind)| {
+            kind.explosion
+                .bursts()
+                .iter()
+                .any(|burst| burst.shots.iter().any(|shot| shot.distance == 0))
+        }) {
+            return Err(format!("target kind {} has a shot at distance 0", name));
+        }
+        let names = self
+            .targets
+            .iter()
+            .map(|t| &t.kind)
+            .chain(self.spawn.kinds.iter().map(|(name, _)| name));
+        for name in names {
+            if self.target_kind(name).is_none() {
+                return Err(format!("unknown target kind {}", name));
+            }
+        }
+        if !self.spawn.kinds.is_empty() && self.spawn.kinds.iter().all(|(_, w)| *w == 0) {
+            return Err("spawn kinds need a weight greater than 0".into());
+        }
+        Ok(())
+    }
+
+    // normalは定義しなければデフォルトの種類になる
+    pub fn target_kind(&self, name: &str) -> Option<TargetKind> {
+        self.kinds
+            .get(name)
+            .cloned()
+            .or_else(|| (name == DEFAULT_TARGET_KIND).then(TargetKind::default))
+    }
+
+    // 面で使える種類の名前。normalが先頭
+    pub fn target_kind_names(&self) -> Vec<String> {
+        let mut names = vec![default_target_kind()];
+        names.extend(
+            self.kinds
+                .keys()
+                .filter(|name| *name != DEFAULT_TARGET_KIND)
+                .cloned(),
+        );
+        names
+    }
+}
+
+// 順に遊ぶ面
+#[derive(Resource, Debug, Clone, PartialEq)]
+pub struct Levels {
+    pub levels: Vec<Level>,
+}
+
+impl Default for Levels {
+    fn default() -> Self {
+        Self::endless(Arena::default())
+    }
+}
+
+impl Levels {
+    pub fn endless(arena: Arena) -> Self {
+        Self {
+            levels: vec![Level::endless(arena)],
+        }
+    }
+
+    // ディレクトリの中の.ronファイルをファイル名の順に読み込む
+    pub fn load_dir(dir: &Path) -> io::Result<Self> {
+        let mut paths: Vec<_> = fs::read_dir(dir)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<io::Result<_>>()?;
+        paths.retain(|path| path.extension().is_some_and(|ext| ext == LEVEL_EXTENSION));
+        paths.sort();
+        if paths.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "no level files in the directory",
+            ));
+        }
+        let levels = paths
+            .iter()
+            .map(|path| {
+                Level::load(path)
+                    .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))
+            })
+            .collect::<io::Result<_>>()?;
+        Ok(Self { levels })
+    }
+
+    pub fn get(&self, index: usize) -> &Level {
+        &self.levels[index.min(self.levels.len() - 1)]
+    }
+
+    pub fn is_last(&self, index: usize) -> bool {
+        index + 1 >= self.levels.len()
+    }
+
+    // すべての面が収まる画面
+    pub fn screen(&self) -> Screen {
+        Screen::fit(self.levels.iter().map(|level| &level.arena))
+    }
+
+    // 面の内容から作るハッシュ。リプレイが同じ面で記録されたかを確かめるのに使う
+    // 実行環境によって変わらないように、RONにした文字列のFNV-1aにする
+    pub fn hash(&self) -> u64 {
+        let text = ron::to_string(&self.levels).expect("levels can be serialized");
+        text.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::{Burst, Direction, Explosion, Shot};
+
+    fn level() -> Level {
+        Level::endless(Arena::default())
+    }
+
+    #[test]
+    fn endless_level_is_valid() {
+        assert_eq!(level().validate(), Ok(()));
+    }
+
+    #[test]
+    fn rejects_target_outside_or_on_wall() {
+        let mut level = level();
+        level.targets = vec![PlacedTarget::new(
+            Position::new(level.arena.x_max + 1, 5),
+            DEFAULT_TARGET_KIND,
+        )];
+        assert!(level.validate().is_err());
+
+        let mut level = self::level();
+        level.arena.walls = vec![Position::new(5, 5)];
+        level.arena.update_walls();
+        level.targets = vec![PlacedTarget::new(Position::new(5, 5), DEFAULT_TARGET_KIND)];
+        assert!(level.validate().is_err());
+    }
+
+    #[test]
+    fn rejects_bad_spawn_rule() {
+        let mut level = level();
+        level.spawn.chance = 1.5;
+        assert!(level.validate().is_err());
+
+        let mut level = self::level();
+        level.spawn.kinds = vec![(DEFAULT_TARGET_KIND.into(), 0)];
+        assert!(level.validate().is_err());
+    }
+
+    #[test]
+    fn rejects_unknown_or_broken_kinds() {
+        let mut level = level();
+        level.targets = vec![PlacedTarget::new(Position::new(5, 5), "hard")];
+        assert!(level.validate().is_err());
+        level
+            .kinds
+            .insert("hard".into(), TargetKind { hp: 3, ..default() });
+        assert_eq!(level.validate(), Ok(()));
+
+        level.kinds.get_mut("hard").unwrap().hp = 0;
+        assert!(level.validate().is_err());
+
+        level.kinds.get_mut("hard").unwrap().hp = 3;
+        level.kinds.get_mut("hard").unwrap().explosion = Explosion::Bursts(vec![Burst {
+            delay: 0,
+            shots: vec![Shot {
+                direction: Direction::Up,
+                distance: 0,
+                dust: false,
+            }],
+        }]);
+        assert!(level.validate().is_err());
+    }
+
+    #[test]
+    fn rejects_invalid_arena() {
+        let mut level = level();
+        level.arena.x_min = 1;
+        assert!(level.validate().is_err());
+    }
+}
@@ -0,0 +1,110 @@
+// Bevyのsystemはクエリの型が複雑になりがちなので許容する
+#![allow(clippy::type_complexity)]
+
+pub mod actions;
+pub mod arena;
+pub mod assets;
+pub mod components;
+pub mod controls;
+pub mod editor;
+pub mod events;
+pub mod font;
+pub mod gamepad;
+pub mod gameplay;
+pub mod graphics;
+pub mod headless;
+pub mod hiscore;
+pub mod hud;
+pub mod level;
+pub mod name_entry;
+pub mod pause;
+pub mod replay;
+pub mod resources;
+pub mod ron_file;
+pub mod sound;
+pub mod states;
+pub mod title;
+
+use std::path::PathBuf;
+
+use actions::ActionPlugin;
+use bevy::prelude::*;
+use controls::ControlsPlugin;
+use editor::{default_editor_path, EditorPlugin};
+use font::FontPlugin;
+use gamepad::GamepadPlugin;
+use gameplay::GameplayPlugin;
+use graphics::GraphicsPlugin;
+use hiscore::HiScorePlugin;
+use hud::HudPlugin;
+use level::Levels;
+use pause::PausePlugin;
+use sound::SoundPlugin;
+use states::GameState;
+use title::TitlePlugin;
+
+pub const TITLE: &str = "u235";
+// 1セルのピクセル数。画像はこの大きさで描かれている
+pub const CELL_SIZE_PX: f32 = 16.0;
+// ゲームロジックの1秒あたりのtick数のデフォルト
+pub const TICK_RATE: f64 = 30.0;
+// 残機のデフォルト
+pub const LIVES: u32 = 3;
+
+// ゲーム全体をまとめたplugin
+// headlessの場合はゲームロジックだけを追加してすぐにゲームを始める
+// そうでなければ描画・音・HUD・ハイスコア表・タイトル画面も追加し、タイトル画面から始める
+#[derive(Default)]
+pub struct U235Plugin {
+    pub headless: bool,
+    // 乱数のseed。指定しなければランダム
+    pub seed: Option<u64>,
+    // 1秒あたりのtick数。指定しなければTICK_RATE
+    pub tick_rate: Option<f64>,
+    // ゲームを始めるときの残機。指定しなければLIVES
+    pub lives: Option<u32>,
+    // 順に遊ぶ面。ウィンドウの大きさもこれで決まる
+    pub levels: Levels,
+    // エディタで編集する面のファイル。指定しなければデータディレクトリのeditor.ron
+    pub edit_path: Option<PathBuf>,
+}
+
+impl Plugin for U235Plugin {
+    fn build(&self, app: &mut App) {
+        if self.headless {
+            app.add_plugins((
+                ActionPlugin { path: None },
+                GameplayPlugin {
+                    seed: self.seed,
+                    initial_state: GameState::Playing,
+                    tick_rate: self.tick_rate,
+                    lives: self.lives,
+                    levels: self.levels.clone(),
+                },
+            ));
+        } else {
+            app.add_plugins((
+                ActionPlugin::default(),
+                GamepadPlugin,
+                GameplayPlugin {
+                    seed: self.seed,
+                    initial_state: GameState::Title,
+                    tick_rate: self.tick_rate,
+                    lives: self.lives,
+                    levels: self.levels.clone(),
+                },
+                GraphicsPlugin,
+                FontPlugin,
+                SoundPlugin,
+                HudPlugin,
+                HiScorePlugin::default(),
+                TitlePlugin,
+                PausePlugin,
+                ControlsPlugin,
+                EditorPlugin {
+                    path: self.edit_path.clone().or_else(default_editor_path),
+                },
+            ));
+        }
+    }
+}
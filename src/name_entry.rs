@@ -0,0 +1,171 @@
+use bevy::prelude::*;
+
+use crate::actions::*;
+use crate::arena::*;
+use crate::font::*;
+use crate::hiscore::HiScores;
+use crate::hud::{spawn_centered_text, spawn_panel, spawn_text};
+use crate::resources::*;
+use crate::states::*;
+
+pub const NAME_LENGTH: usize = 3;
+const TOP: i32 = 6;
+const PROMPT: &str = "ENTER YOUR INITIALS";
+// 名前に使える文字
+const NAME_LETTERS: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZ.-";
+
+// 入力中の名前。各文字はNAME_LETTERSの添字
+#[derive(Resource, Default)]
+struct NameEntry {
+    letters: [usize; NAME_LENGTH],
+    slot: usize,
+}
+
+impl NameEntry {
+    fn name(&self) -> String {
+        self.letters
+            .iter()
+            .map(|i| NAME_LETTERS.as_bytes()[*i] as char)
+            .collect()
+    }
+}
+
+#[derive(Component)]
+struct NameEntryScreen;
+
+#[derive(Component)]
+struct NameEntrySlot(usize);
+
+// ハイスコア表に入ったときのアーケード風の名前入力
+// 左右で文字を選び、Fireで確定して次の文字に進む
+pub struct NameEntryPlugin;
+
+impl Plugin for NameEntryPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<NameEntry>()
+            .add_systems(OnEnter(GameState::NameEntry), name_entry_enter)
+            .add_systems(OnExit(GameState::NameEntry), name_entry_exit)
+            .add_systems(
+                Update,
+                (name_entry_system, name_entry_slot_system)
+                    .chain()
+                    .run_if(in_state(GameState::NameEntry)),
+            );
+    }
+}
+
+fn name_entry_enter(
+    mut commands: Commands,
+    mut name_entry: ResMut<NameEntry>,
+    game: Res<Game>,
+    bindings: Res<Bindings>,
+    screen: Res<Screen>,
+) {
+    *name_entry = NameEntry::default();
+
+    let mut entities = vec![spawn_panel(&screen, TOP - 1, 9, &mut commands)];
+    let score = game.score.to_string();
+    let help = format!(
+        "{} {} - SELECT   {} - OK",
+        bindings.bindings.keys_label(Action::MoveLeft, "/"),
+        bindings.bindings.keys_label(Action::MoveRight, "/"),
+        bindings.bindings.keys_label(Action::Fire, "/"),
+    );
+    let texts = [
+        (PROMPT, TOP),
+        (score.as_str(), TOP + 2),
+        (help.as_str(), TOP + 6),
+    ];
+    for (text, y) in texts {
+        entities.push(spawn_centered_text(
+            text,
+            y,
+            Color::WHITE,
+            &screen,
+            &mut commands,
+        ));
+    }
+    for entity in entities {
+        commands.entity(entity).insert(NameEntryScreen);
+    }
+
+    // 1文字ずつ1セルおきに、画面の中央に並べる
+    let name_x = screen.columns / 2 - NAME_LENGTH as i32 / 2;
+    for slot in 0..NAME_LENGTH {
+        let entity = spawn_text(
+            "",
+            name_x + slot as i32,
+            TOP + 4,
+            Color::WHITE,
+            &mut commands,
+        );
+        commands
+            .entity(entity)
+            .insert((NameEntryScreen, NameEntrySlot(slot)));
+    }
+}
+
+fn name_entry_exit(mut commands: Commands, query: Query<Entity, With<NameEntryScreen>>) {
+    for entity in &query {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+fn name_entry_system(
+    actions: Res<ActionState>,
+    mut name_entry: ResMut<NameEntry>,
+    mut hiscores: ResMut<HiScores>,
+    game: Res<Game>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    let count = NAME_LETTERS.len();
+    let slot = name_entry.slot;
+    if actions.just_pressed(Action::MoveLeft) {
+        name_entry.letters[slot] = (name_entry.letters[slot] + count - 1) % count;
+    }
+    if actions.just_pressed(Action::MoveRight) {
+        name_entry.letters[slot] = (name_entry.letters[slot] + 1) % count;
+    }
+    if actions.just_pressed(Action::Fire) {
+        if slot + 1 < NAME_LENGTH {
+            // 次の文字は今の文字から選び始める
+            name_entry.letters[slot + 1] = name_entry.letters[slot];
+            name_entry.slot += 1;
+        } else {
+            hiscores.record(name_entry.name(), &game);
+            hiscores.entering_name = false;
+            next_state.set(GameState::GameOver);
+        }
+    }
+}
+
+// 入力中の文字は黄色で点滅させ、まだ入力していない文字は隠す
+fn name_entry_slot_system(
+    name_entry: Res<NameEntry>,
+    time: Res<Time>,
+    mut query: Query<(&NameEntrySlot, &mut BitmapText, &mut Visibility)>,
+) {
+    let blink = time.elapsed_seconds() % 0.5 < 0.25;
+    for (slot, mut text, mut visibility) in &mut query {
+        let letter = NAME_LETTERS.as_bytes()[name_entry.letters[slot.0]] as char;
+        let color = if slot.0 == name_entry.slot {
+            Color::YELLOW
+        } else {
+            Color::WHITE
+        };
+        text.set_if_neq(BitmapText::new(letter, color));
+        if slot.0 == name_entry.slot {
+            *visibility = if blink {
+                Visibility::Visible
+            } else {
+                Visibility::Hidden
+            };
+        } else {
+            *visibility = if slot.0 < name_entry.slot {
+                Visibility::Visible
+            } else {
+                Visibility::Hidden
+            };
+        }
+    }
+}
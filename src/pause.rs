@@ -0,0 +1,170 @@
+use bevy::app::AppExit;
+use bevy::prelude::*;
+
+use crate::actions::*;
+use crate::arena::*;
+use crate::components::*;
+use crate::graphics::position_to_transform;
+use crate::hud::{spawn_centered_text, spawn_panel};
+use crate::states::*;
+
+const PAUSE_TEXT: &str = "PAUSED";
+const QUIT_TEXT: &str = "QUIT GAME";
+const QUIT_HELP: &str = "Y - YES   N - NO";
+const PAUSE_TOP: i32 = 9;
+const QUIT_TOP: i32 = 14;
+
+// 終了確認を表示中ならtrue
+#[derive(Resource, Default)]
+struct QuitDialog(bool);
+
+#[derive(Component)]
+struct PauseScreen;
+
+#[derive(Component)]
+struct QuitDialogScreen;
+
+// Pause(P)でポーズ・再開し、Quit(Esc)では終了確認を出す
+// ポーズ中はゲームロジックが止まり、画面を暗くする
+pub struct PausePlugin;
+
+impl Plugin for PausePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<QuitDialog>()
+            .add_systems(OnEnter(GameState::Paused), pause_enter)
+            .add_systems(OnExit(GameState::Paused), pause_exit)
+            .add_systems(OnEnter(GameState::Playing), close_quit_dialog)
+            .add_systems(OnEnter(GameState::Attract), close_quit_dialog)
+            .add_systems(
+                Update,
+                (
+                    pause_system
+                        .run_if(in_state(GameState::Playing).or_else(in_state(GameState::Paused))),
+                    // 操作設定画面とエディタではEscで戻る
+                    quit_system.run_if(not(
+                        in_state(GameState::Controls).or_else(in_state(GameState::Editor))
+                    )),
+                    quit_dialog_system.run_if(resource_changed::<QuitDialog>),
+                )
+                    .chain(),
+            );
+    }
+}
+
+fn pause_enter(mut commands: Commands, bindings: Res<Bindings>, screen: Res<Screen>) {
+    // 画面全体を暗くする
+    let mut overlay_pos = position_to_transform(Position::new(0, 0));
+    overlay_pos.translation.z = 4.0;
+    let mut entities = vec![commands
+        .spawn(SpriteBundle {
+            sprite: Sprite {
+                color: Color::rgba(0.0, 0.0, 0.0, 0.6),
+                anchor: bevy::sprite::Anchor::TopLeft,
+                custom_size: Some(screen.size()),
+                ..default()
+            },
+            transform: overlay_pos,
+            ..default()
+        })
+        .id()];
+    let help = format!(
+        "{} - RESUME   {} - QUIT",
+        bindings.bindings.keys_label(Action::Pause, "/"),
+        bindings.bindings.keys_label(Action::Quit, "/"),
+    );
+    for (text, y) in [(PAUSE_TEXT, PAUSE_TOP), (help.as_str(), PAUSE_TOP + 2)] {
+        entities.push(spawn_centered_text(
+            text,
+            y,
+            Color::WHITE,
+            &screen,
+            &mut commands,
+        ));
+    }
+    for entity in entities {
+        commands.entity(entity).insert(PauseScreen);
+    }
+}
+
+fn pause_exit(mut commands: Commands, query: Query<Entity, With<PauseScreen>>) {
+    for entity in &query {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+fn pause_system(
+    actions: Res<ActionState>,
+    quit_dialog: Res<QuitDialog>,
+    state: Res<State<GameState>>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if quit_dialog.0 || !actions.just_pressed(Action::Pause) {
+        return;
+    }
+    if *state.get() == GameState::Playing {
+        next_state.set(GameState::Paused);
+    } else {
+        next_state.set(GameState::Playing);
+    }
+}
+
+// プレイ中にQuitを押したらポーズしてから確認する
+fn quit_system(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    actions: Res<ActionState>,
+    mut quit_dialog: ResMut<QuitDialog>,
+    state: Res<State<GameState>>,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut app_exit_events: EventWriter<AppExit>,
+) {
+    if !quit_dialog.0 {
+        if actions.just_pressed(Action::Quit) {
+            if *state.get() == GameState::Playing {
+                next_state.set(GameState::Paused);
+            }
+            quit_dialog.0 = true;
+        }
+    } else if keyboard_input.just_pressed(KeyCode::KeyY) {
+        app_exit_events.send(AppExit);
+    } else if keyboard_input.just_pressed(KeyCode::KeyN) || actions.just_pressed(Action::Quit) {
+        quit_dialog.0 = false;
+    }
+}
+
+fn close_quit_dialog(mut quit_dialog: ResMut<QuitDialog>) {
+    if quit_dialog.0 {
+        quit_dialog.0 = false;
+    }
+}
+
+// 確認画面はリスタートやタイトル画面に移っても消えないようにDespawnOnRestartを外す
+fn quit_dialog_system(
+    mut commands: Commands,
+    quit_dialog: Res<QuitDialog>,
+    screen: Res<Screen>,
+    query: Query<Entity, With<QuitDialogScreen>>,
+) {
+    for entity in &query {
+        commands.entity(entity).despawn_recursive();
+    }
+    if !quit_dialog.0 {
+        return;
+    }
+
+    let mut entities = vec![spawn_panel(&screen, QUIT_TOP - 1, 5, &mut commands)];
+    for (text, y) in [(QUIT_TEXT, QUIT_TOP), (QUIT_HELP, QUIT_TOP + 2)] {
+        entities.push(spawn_centered_text(
+            text,
+            y,
+            Color::YELLOW,
+            &screen,
+            &mut commands,
+        ));
+    }
+    for entity in entities {
+        commands
+            .entity(entity)
+            .remove::<DespawnOnRestart>()
+            .insert(QuitDialogScreen);
+    }
+}
@@ -0,0 +1,419 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use bevy::app::AppExit;
+use bevy::prelude::*;
+
+use crate::gameplay::{action_intent_system, GameplaySet, InputSet};
+use crate::level::Levels;
+use crate::resources::*;
+use crate::states::*;
+
+pub const GAME_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+// リプレイファイルの先頭。最後の数字はゲームの進み方が変わったら上げる
+// 古い番号のファイルは同じゲームを再生できないので読まない
+const REPLAY_MAGIC: &[u8; 8] = b"U235RPL4";
+const REPLAY_MAGIC_PREFIX: &[u8; 7] = b"U235RPL";
+
+const INTENT_LEFT: u8 = 1 << 0;
+const INTENT_RIGHT: u8 = 1 << 1;
+const INTENT_FIRE: u8 = 1 << 2;
+const INTENT_SWITCH_WEAPON: u8 = 1 << 3;
+
+// 1ゲーム分の操作の記録
+// ファイル形式(数値はlittle endian):
+//   magic "U235RPL4"
+//   ゲームのバージョン文字列の長さ(u8)とその文字列
+//   seed(u64)
+//   最初の残機(u8)
+//   面のハッシュ(u64)。Levels::hash
+//   tick数(u32)
+//   1tickにつき1byteの操作(bit0: left, bit1: right, bit2: fire, bit3: switch weapon)
+#[derive(Debug, Clone, Default)]
+pub struct Replay {
+    pub version: String,
+    pub seed: u64,
+    pub lives: LivesRule,
+    pub levels_hash: u64,
+    pub intents: Vec<PlayerIntent>,
+}
+
+impl Replay {
+    pub fn new(seed: u64, lives: LivesRule, levels: &Levels) -> Self {
+        Self {
+            version: GAME_VERSION.to_string(),
+            seed,
+            lives,
+            levels_hash: levels.hash(),
+            intents: Vec::new(),
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let version = self.version.as_bytes();
+        let mut bytes =
+            Vec::with_capacity(8 + 1 + version.len() + 8 + 1 + 8 + 4 + self.intents.len());
+        bytes.extend_from_slice(REPLAY_MAGIC);
+        bytes.push(version.len() as u8);
+        bytes.extend_from_slice(version);
+        bytes.extend_from_slice(&self.seed.to_le_bytes());
+        bytes.push(self.lives.lives.min(u8::MAX as u32) as u8);
+        bytes.extend_from_slice(&self.levels_hash.to_le_bytes());
+        bytes.extend_from_slice(&(self.intents.len() as u32).to_le_bytes());
+        for intent in &self.intents {
+            bytes.push(intent_to_bits(intent));
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> io::Result<Self> {
+        let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message);
+        let mut reader = bytes;
+        let mut take = |n: usize| {
+            if reader.len() < n {
+                return Err(invalid("replay file is truncated"));
+            }
+            let (head, tail) = reader.split_at(n);
+            reader = tail;
+            Ok(head)
+        };
+
+        let magic = take(REPLAY_MAGIC.len())?;
+        if magic != REPLAY_MAGIC {
+            return Err(if magic.starts_with(REPLAY_MAGIC_PREFIX) {
+                invalid("replay file is from an incompatible version of the game")
+            } else {
+                invalid("not a replay file")
+            });
+        }
+        let version_len = take(1)?[0] as usize;
+        let version = String::from_utf8(take(version_len)?.to_vec())
+            .map_err(|_| invalid("invalid version string"))?;
+        let seed = u64::from_le_bytes(take(8)?.try_into().unwrap());
+        let lives = LivesRule {
+            lives: take(1)?[0].max(1) as u32,
+        };
+        let levels_hash = u64::from_le_bytes(take(8)?.try_into().unwrap());
+        let count = u32::from_le_bytes(take(4)?.try_into().unwrap()) as usize;
+        let intents = take(count)?
+            .iter()
+            .map(|bits| intent_from_bits(*bits))
+            .collect();
+        Ok(Self {
+            version,
+            seed,
+            lives,
+            levels_hash,
+            intents,
+        })
+    }
+
+    // 記録したときと同じ面でなければ同じゲームを再生できない
+    pub fn check_levels(&self, levels: &Levels) -> io::Result<()> {
+        if self.levels_hash != levels.hash() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "replay was recorded with different levels or arena",
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, self.to_bytes())
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        Self::from_bytes(&fs::read(path)?)
+    }
+}
+
+fn intent_to_bits(intent: &PlayerIntent) -> u8 {
+    let mut bits = 0;
+    if intent.left {
+        bits |= INTENT_LEFT;
+    }
+    if intent.right {
+        bits |= INTENT_RIGHT;
+    }
+    if intent.fire {
+        bits |= INTENT_FIRE;
+    }
+    if intent.switch_weapon {
+        bits |= INTENT_SWITCH_WEAPON;
+    }
+    bits
+}
+
+fn intent_from_bits(bits: u8) -> PlayerIntent {
+    PlayerIntent {
+        left: bits & INTENT_LEFT != 0,
+        right: bits & INTENT_RIGHT != 0,
+        fire: bits & INTENT_FIRE != 0,
+        switch_weapon: bits & INTENT_SWITCH_WEAPON != 0,
+    }
+}
+
+// 記録中のゲーム。ゲームオーバーになるか、途中で終了したらpathに保存する
+// 2つめからのゲームはファイル名に番号を付けて別のファイルにする
+#[derive(Resource)]
+pub struct ReplayRecorder {
+    pub path: PathBuf,
+    pub replay: Replay,
+    // まだ保存していないゲームを記録中ならtrue
+    recording: bool,
+    // 保存したゲームの数
+    saved_games: u32,
+}
+
+impl ReplayRecorder {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            replay: Replay::default(),
+            recording: false,
+            saved_games: 0,
+        }
+    }
+
+    // n番目(1から)のゲームのパス。best.u235の2つめはbest-2.u235
+    pub fn game_path(&self, n: u32) -> PathBuf {
+        if n <= 1 {
+            return self.path.clone();
+        }
+        let stem = self.path.file_stem().unwrap_or_default().to_string_lossy();
+        let name = match self.path.extension() {
+            Some(ext) => format!("{}-{}.{}", stem, n, ext.to_string_lossy()),
+            None => format!("{}-{}", stem, n),
+        };
+        self.path.with_file_name(name)
+    }
+
+    fn save(&mut self) {
+        if !self.recording {
+            return;
+        }
+        self.recording = false;
+        self.saved_games += 1;
+        let path = self.game_path(self.saved_games);
+        if let Err(e) = self.replay.save(&path) {
+            warn!("failed to save replay {}: {}", path.display(), e);
+        }
+    }
+}
+
+// パニックで終わったときも、記録中のゲームを残す
+impl Drop for ReplayRecorder {
+    fn drop(&mut self) {
+        self.save();
+    }
+}
+
+// 再生中のリプレイ。キーボードの代わりにこの操作をゲームに渡す
+#[derive(Resource)]
+pub struct ReplayPlayer {
+    pub replay: Replay,
+}
+
+// 操作の記録と再生
+// recordを指定するとゲームオーバーのたびと終了時にそのゲームを保存する
+// playbackを指定するとキーボードの代わりにリプレイの操作でゲームを進める
+#[derive(Default)]
+pub struct ReplayPlugin {
+    pub record: Option<PathBuf>,
+    pub playback: Option<Replay>,
+}
+
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut App) {
+        if let Some(path) = &self.record {
+            app.insert_resource(ReplayRecorder::new(path.clone()))
+                .add_systems(
+                    OnEnter(GameState::Playing),
+                    start_recording
+                        .after(GameplaySet)
+                        .run_if(not_resuming)
+                        .run_if(not_advancing),
+                )
+                .add_systems(
+                    FixedUpdate,
+                    record_system
+                        .in_set(GameplaySet)
+                        .after(InputSet)
+                        .run_if(in_state(GameState::Playing)),
+                )
+                .add_systems(OnEnter(GameState::GameOver), save_recording)
+                .add_systems(Last, save_recording.run_if(on_event::<AppExit>()));
+        }
+
+        if let Some(replay) = &self.playback {
+            app.insert_resource(ReplayPlayer {
+                replay: replay.clone(),
+            })
+            .add_systems(Startup, warn_version)
+            .add_systems(
+                OnEnter(GameState::Playing),
+                start_playback
+                    .before(GameplaySet)
+                    .run_if(not_resuming)
+                    .run_if(not_advancing),
+            )
+            .add_systems(
+                FixedUpdate,
+                playback_system
+                    .in_set(InputSet)
+                    .after(action_intent_system)
+                    .run_if(in_state(GameState::Playing)),
+            );
+        }
+    }
+}
+
+fn start_recording(
+    game: Res<Game>,
+    lives_rule: Res<LivesRule>,
+    levels: Res<Levels>,
+    mut recorder: ResMut<ReplayRecorder>,
+) {
+    recorder.replay = Replay::new(game.seed, *lives_rule, &levels);
+    recorder.recording = true;
+}
+
+fn record_system(intent: Res<PlayerIntent>, mut recorder: ResMut<ReplayRecorder>) {
+    recorder.replay.intents.push(*intent);
+}
+
+fn save_recording(mut recorder: ResMut<ReplayRecorder>) {
+    recorder.save();
+}
+
+fn warn_version(player: Res<ReplayPlayer>) {
+    if player.replay.version != GAME_VERSION {
+        warn!(
+            "replay was recorded with version {}, current version is {}",
+            player.replay.version, GAME_VERSION
+        );
+    }
+}
+
+// ゲーム開始時にリプレイのseedで乱数を初期化し、残機を合わせる
+fn start_playback(
+    player: Res<ReplayPlayer>,
+    mut rng: ResMut<GameRng>,
+    mut lives_rule: ResMut<LivesRule>,
+) {
+    rng.set_next_seed(player.replay.seed);
+    *lives_rule = player.replay.lives;
+}
+
+// 記録が尽きたら何も操作しない
+fn playback_system(game: Res<Game>, player: Res<ReplayPlayer>, mut intent: ResMut<PlayerIntent>) {
+    *intent = player
+        .replay
+        .intents
+        .get(game.ticks as usize)
+        .copied()
+        .unwrap_or_default();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arena::Arena;
+
+    fn replay() -> Replay {
+        let mut replay = Replay::new(
+            0x0123_4567_89ab_cdef,
+            LivesRule { lives: 5 },
+            &Levels::default(),
+        );
+        replay.intents = vec![
+            PlayerIntent::default(),
+            PlayerIntent {
+                left: true,
+                fire: true,
+                ..default()
+            },
+            PlayerIntent {
+                right: true,
+                switch_weapon: true,
+                ..default()
+            },
+        ];
+        replay
+    }
+
+    #[test]
+    fn round_trip() {
+        let replay = replay();
+        let loaded = Replay::from_bytes(&replay.to_bytes()).unwrap();
+        assert_eq!(loaded.version, replay.version);
+        assert_eq!(loaded.seed, replay.seed);
+        assert_eq!(loaded.lives.lives, 5);
+        assert_eq!(loaded.levels_hash, replay.levels_hash);
+        assert_eq!(loaded.intents, replay.intents);
+    }
+
+    #[test]
+    fn rejects_bad_magic() {
+        let mut bytes = replay().to_bytes();
+        bytes[..8].copy_from_slice(b"NOTRPL00");
+        let error = Replay::from_bytes(&bytes).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(error.to_string(), "not a replay file");
+    }
+
+    #[test]
+    fn rejects_old_version() {
+        let mut bytes = replay().to_bytes();
+        bytes[..8].copy_from_slice(b"U235RPL1");
+        let error = Replay::from_bytes(&bytes).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "replay file is from an incompatible version of the game"
+        );
+    }
+
+    #[test]
+    fn rejects_other_levels() {
+        let replay = replay();
+        assert!(replay.check_levels(&Levels::default()).is_ok());
+        let mut arena = Arena::default();
+        arena.y_max -= 1;
+        let error = replay.check_levels(&Levels::endless(arena)).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn rejects_truncated_file() {
+        let bytes = replay().to_bytes();
+        // 最後の1tick分や、ヘッダーの途中で切れている
+        for len in [bytes.len() - 1, 12, 4, 0] {
+            let error = Replay::from_bytes(&bytes[..len]).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        }
+    }
+
+    #[test]
+    fn numbers_later_games() {
+        let recorder = ReplayRecorder::new(PathBuf::from("replays/best.u235"));
+        assert_eq!(recorder.game_path(1), PathBuf::from("replays/best.u235"));
+        assert_eq!(recorder.game_path(2), PathBuf::from("replays/best-2.u235"));
+        let recorder = ReplayRecorder::new(PathBuf::from("best"));
+        assert_eq!(recorder.game_path(3), PathBuf::from("best-3"));
+    }
+
+    #[test]
+    fn saves_unfinished_game_when_dropped() {
+        let path = std::env::temp_dir().join(format!("u235-test-{}.u235", std::process::id()));
+        let mut recorder = ReplayRecorder::new(path.clone());
+        recorder.replay = replay();
+        recorder.recording = true;
+        drop(recorder);
+        let loaded = Replay::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.intents, replay().intents);
+    }
+}
@@ -1,4 +1,11 @@
+use std::collections::{BTreeMap, HashMap};
+
 use bevy::prelude::*;
+use rand::rngs::StdRng;
+use rand::{RngCore, SeedableRng};
+
+use crate::components::{Chain, Position};
+use crate::LIVES;
 
 #[derive(Resource)]
 pub struct HitSound(pub Handle<AudioSource>);
@@ -10,19 +17,197 @@ pub struct CrashSound(pub Handle<AudioSource>);
 pub struct Game {
     pub score: i32,
     pub hi_score: i32,
+    pub ticks: u32,
+    pub seed: u64,
+    // 今の面の番号。0から
+    pub level: usize,
+    // 今の面を始めてからのtick数
+    pub level_ticks: u32,
+    // 今の面を始めたときのスコア
+    pub level_start_score: i32,
+    // 残機。今のプレイヤーも含む
+    pub lives: u32,
+    // このスコアに達したら残機が増える
+    pub next_extra_life: i32,
+    pub chains: Chains,
 }
 
 impl Game {
-    pub fn reset(&mut self) {
+    pub fn reset(&mut self, lives: u32) {
         self.score = 0;
+        self.ticks = 0;
+        self.level = 0;
+        self.level_ticks = 0;
+        self.level_start_score = 0;
+        self.lives = lives;
+        self.next_extra_life = EXTRA_LIFE_FIRST_SCORE;
+        self.chains.clear();
+    }
+
+    // 今の面で取ったスコア
+    pub fn level_score(&self) -> i32 {
+        self.score - self.level_start_score
+    }
+}
+
+// 続いている連鎖
+// 連鎖はプレイヤーの1回の射撃から始まり、その弾や破裂の弾が飛んでいる間続く
+#[derive(Debug, Default)]
+pub struct Chains {
+    next_id: u32,
+    combos: BTreeMap<u32, Combo>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Combo {
+    // 壊したターゲットの数
+    pub count: u32,
+    // 最後にターゲットを壊したtick
+    pub last_tick: u32,
+}
+
+impl Chains {
+    pub fn start(&mut self) -> Chain {
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1);
+        Chain { id, depth: 0 }
+    }
+
+    // 壊したターゲットを数え、その連鎖のコンボ数を返す
+    pub fn add_combo(&mut self, chain: &Chain, tick: u32) -> u32 {
+        let combo = self.combos.entry(chain.id).or_insert(Combo {
+            count: 0,
+            last_tick: tick,
+        });
+        combo.count += 1;
+        combo.last_tick = tick;
+        combo.count
+    }
+
+    // 続いている連鎖のidとコンボ
+    pub fn combos(&self) -> impl Iterator<Item = (u32, &Combo)> {
+        self.combos.iter().map(|(id, combo)| (*id, combo))
+    }
+
+    pub fn max_combo(&self) -> u32 {
+        self.combos
+            .values()
+            .map(|combo| combo.count)
+            .max()
+            .unwrap_or(0)
+    }
+
+    // 連鎖を終わりにして、そのコンボ数を返す
+    pub fn end(&mut self, id: u32) -> u32 {
+        self.combos.remove(&id).map_or(0, |combo| combo.count)
+    }
+
+    // すべての連鎖を終わりにして、それぞれのコンボ数を返す
+    pub fn end_all(&mut self) -> Vec<u32> {
+        std::mem::take(&mut self.combos)
+            .into_values()
+            .map(|combo| combo.count)
+            .collect()
+    }
+
+    pub fn clear(&mut self) {
+        self.combos.clear();
+    }
+}
+
+// 最初の残機が増えるスコアと、その後に残機が増える間隔
+pub const EXTRA_LIFE_FIRST_SCORE: i32 = 20000;
+pub const EXTRA_LIFE_INTERVAL: i32 = 50000;
+
+// ゲームを始めるときの残機
+#[derive(Resource, Debug, Clone, Copy)]
+pub struct LivesRule {
+    pub lives: u32,
+}
+
+impl Default for LivesRule {
+    fn default() -> Self {
+        Self { lives: LIVES }
+    }
+}
+
+// そのtickのプレイヤーの操作
+// キーボードやリプレイから設定され、ゲームロジックはこれだけを見る
+#[derive(Resource, Debug, Default, Clone, Copy, Eq, PartialEq)]
+pub struct PlayerIntent {
+    pub left: bool,
+    pub right: bool,
+    pub fire: bool,
+    // 押した瞬間だけtrue
+    pub switch_weapon: bool,
+}
+
+// ゲーム内の乱数はすべてこれを使う
+// 同じseedと同じ入力なら同じゲームが再現される
+#[derive(Resource)]
+pub struct GameRng {
+    next_seed: u64,
+    // デモのseedはゲームのseedとは別に進める
+    next_demo_seed: u64,
+    rng: StdRng,
+}
+
+impl GameRng {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            next_seed: seed,
+            next_demo_seed: !seed,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    pub fn set_next_seed(&mut self, seed: u64) {
+        self.next_seed = seed;
+    }
+
+    // 次のゲームのseedで乱数を初期化し、そのseedを返す
+    // ゲームごとにseedを1ずつ進める
+    pub fn start_game(&mut self) -> u64 {
+        let seed = self.next_seed;
+        self.next_seed = self.next_seed.wrapping_add(1);
+        self.rng = StdRng::seed_from_u64(seed);
+        seed
+    }
+
+    // デモの乱数を初期化し、そのseedを返す。ゲームのseedは進めない
+    pub fn start_demo(&mut self) -> u64 {
+        let seed = self.next_demo_seed;
+        self.next_demo_seed = self.next_demo_seed.wrapping_add(1);
+        self.rng = StdRng::seed_from_u64(seed);
+        seed
+    }
+}
+
+impl RngCore for GameRng {
+    fn next_u32(&mut self) -> u32 {
+        self.rng.next_u32()
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.rng.next_u64()
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.rng.fill_bytes(dest)
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.rng.try_fill_bytes(dest)
     }
 }
 
 #[derive(Resource, Default)]
 pub struct Textures {
     pub back: Handle<Image>,
-    pub bullets: [Handle<Image>; 4],
+    pub bullets: [Handle<Image>; 8],
     pub dust: Handle<Image>,
+    pub font: Handle<Image>,
+    pub font_layout: Handle<TextureAtlasLayout>,
     pub numbers: Handle<Image>,
     pub numbers_layout: Handle<TextureAtlasLayout>,
     pub player: Handle<Image>,
@@ -30,3 +215,87 @@ pub struct Textures {
     pub title: Handle<Image>,
     pub wall: Handle<Image>,
 }
+
+#[derive(Resource)]
+pub struct Headless {
+    pub games: u32,
+    pub max_ticks: u32,
+    pub played: u32,
+}
+
+// セルごとの弾とターゲット
+// 毎tick衝突判定の前に作り直し、衝突判定の中でdespawn・spawnしたものはその場で反映する
+#[derive(Resource, Default)]
+pub struct Occupancy {
+    bullets: HashMap<Position, Vec<Entity>>,
+    targets: HashMap<Position, Vec<Entity>>,
+}
+
+impl Occupancy {
+    pub fn clear(&mut self) {
+        self.bullets.clear();
+        self.targets.clear();
+    }
+
+    pub fn add_bullet(&mut self, position: &Position, entity: Entity) {
+        self.bullets
+            .entry(position.clone())
+            .or_default()
+            .push(entity);
+    }
+
+    pub fn add_target(&mut self, position: &Position, entity: Entity) {
+        self.targets
+            .entry(position.clone())
+            .or_default()
+            .push(entity);
+    }
+
+    pub fn remove_bullet(&mut self, position: &Position, entity: Entity) {
+        remove_entity(&mut self.bullets, position, entity);
+    }
+
+    pub fn remove_target(&mut self, position: &Position, entity: Entity) {
+        remove_entity(&mut self.targets, position, entity);
+    }
+
+    // 弾は追加した順に並ぶ
+    pub fn bullets_at(&self, position: &Position) -> &[Entity] {
+        self.bullets
+            .get(position)
+            .map_or(&[], |entities| entities.as_slice())
+    }
+
+    pub fn targets_at(&self, position: &Position) -> &[Entity] {
+        self.targets
+            .get(position)
+            .map_or(&[], |entities| entities.as_slice())
+    }
+
+    // positionと周りの8セルの弾
+    // 1tickに隣のセルまでしか動かないので、tick中に出会えるのはこの範囲の弾だけ
+    pub fn bullets_near<'a>(&'a self, position: &Position) -> impl Iterator<Item = Entity> + 'a {
+        neighborhood(position).flat_map(|p| self.bullets_at(&p).iter().copied())
+    }
+
+    pub fn targets_near<'a>(&'a self, position: &Position) -> impl Iterator<Item = Entity> + 'a {
+        neighborhood(position).flat_map(|p| self.targets_at(&p).iter().copied())
+    }
+}
+
+fn neighborhood(position: &Position) -> impl Iterator<Item = Position> {
+    let center = position.clone();
+    (-1..=1).flat_map(move |dy| {
+        let center = center.clone();
+        (-1..=1).map(move |dx| center.add(dx, dy))
+    })
+}
+
+fn remove_entity(cells: &mut HashMap<Position, Vec<Entity>>, position: &Position, entity: Entity) {
+    if let Some(entities) = cells.get_mut(position) {
+        entities.retain(|e| *e != entity);
+        if entities.is_empty() {
+            cells.remove(position);
+        }
+    }
+}
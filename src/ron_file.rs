@@ -0,0 +1,31 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+// RONのファイルの読み書き。RONとして読めなければInvalidDataのエラーにする
+
+pub fn load_ron<T: DeserializeOwned>(path: &Path) -> io::Result<T> {
+    let text = fs::read_to_string(path)?;
+    ron::from_str(&text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+// ファイルがなければデフォルトの値を返す
+pub fn load_ron_or_default<T: DeserializeOwned + Default>(path: &Path) -> io::Result<T> {
+    match load_ron(path) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(T::default()),
+        result => result,
+    }
+}
+
+// ディレクトリがなければ作る
+pub fn save_ron<T: Serialize>(path: &Path, value: &T) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let text = ron::ser::to_string_pretty(value, ron::ser::PrettyConfig::default())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    fs::write(path, text)
+}
@@ -0,0 +1,45 @@
+use bevy::prelude::*;
+
+use crate::assets::*;
+use crate::events::*;
+use crate::gameplay::GameplaySet;
+use crate::resources::*;
+
+// 効果音の再生
+pub struct SoundPlugin;
+
+impl Plugin for SoundPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, startup)
+            .add_systems(Update, (hit_event, crash_event).after(GameplaySet));
+    }
+}
+
+fn startup(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.insert_resource(HitSound(asset_server.load(SOUND_HIT)));
+    commands.insert_resource(CrashSound(asset_server.load(SOUND_CRASH)));
+}
+
+fn hit_event(mut commands: Commands, mut hit_events: EventReader<HitEvent>, sound: Res<HitSound>) {
+    if !hit_events.is_empty() {
+        hit_events.clear();
+        commands.spawn(AudioBundle {
+            source: sound.0.clone(),
+            settings: PlaybackSettings::DESPAWN,
+        });
+    }
+}
+
+fn crash_event(
+    mut commands: Commands,
+    mut crash_events: EventReader<CrashEvent>,
+    sound: Res<CrashSound>,
+) {
+    if !crash_events.is_empty() {
+        crash_events.clear();
+        commands.spawn(AudioBundle {
+            source: sound.0.clone(),
+            settings: PlaybackSettings::DESPAWN,
+        });
+    }
+}
@@ -2,7 +2,51 @@ use bevy::prelude::*;
 
 #[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Hash, States)]
 pub enum GameState {
+    Title,
+    // タイトル画面で放置するとゲームが自動で遊ばれるデモ
+    Attract,
     #[default]
     Playing,
+    // プレイ中に一時停止した状態。戻ってもゲームは初期化しない
+    Paused,
+    // キー割り当ての設定画面
+    Controls,
+    NameEntry,
     GameOver,
+    // 面をクリアして次の面を待っている状態
+    LevelClear,
+    // 面のエディタ
+    Editor,
+}
+
+// ゲームが進行している状態(プレイ中とデモ)
+pub fn in_game(state: Res<State<GameState>>) -> bool {
+    matches!(state.get(), GameState::Playing | GameState::Attract)
+}
+
+// ポーズからPlayingに戻るときはtrue
+#[derive(Resource, Default)]
+pub struct Resuming(pub bool);
+
+// OnEnter(Playing)でゲームを始めるsystemはポーズから戻るときには実行しない
+pub fn not_resuming(resuming: Res<Resuming>) -> bool {
+    !resuming.0
+}
+
+// LevelClearからPlayingに進むときはtrue
+#[derive(Resource, Default)]
+pub struct Advancing(pub bool);
+
+// OnEnter(Playing)でゲームの記録を始めるsystemは次の面に進むときには実行しない
+pub fn not_advancing(advancing: Res<Advancing>) -> bool {
+    !advancing.0
+}
+
+// エディタで作っている面を試しに遊んでいるときはtrue
+#[derive(Resource, Default)]
+pub struct Playtesting(pub bool);
+
+// 試しに遊んだゲームはハイスコアにしない
+pub fn not_playtesting(playtesting: Res<Playtesting>) -> bool {
+    !playtesting.0
 }
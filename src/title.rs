@@ -0,0 +1,262 @@
+use bevy::prelude::*;
+
+use crate::actions::*;
+use crate::arena::*;
+use crate::components::{self, *};
+use crate::gameplay::{GameplaySet, InputSet};
+use crate::graphics::{create_top_left_sprite, position_to_transform, TITLE_POSITION};
+use crate::hiscore::HiScores;
+use crate::hud::spawn_centered_text;
+use crate::resources::*;
+use crate::states::*;
+
+// 何も操作しないままこの秒数が経つと、タイトル画面はデモに、ゲームオーバー画面はタイトル画面に切り替わる
+const IDLE_SECONDS: f32 = 10.0;
+// デモはこの秒数で打ち切ってタイトル画面に戻る
+const ATTRACT_SECONDS: f32 = 60.0;
+// タイトル画面に表示するハイスコア表の件数
+const TITLE_HISCORES: usize = 5;
+const PROMPT: &str = "PRESS FIRE TO START";
+const CONTROLS_HELP: &str = "TAB - CONTROLS   E - EDITOR";
+// タイトル画面から操作設定画面・エディタを開くキー
+const CONTROLS_KEY: KeyCode = KeyCode::Tab;
+const EDITOR_KEY: KeyCode = KeyCode::KeyE;
+// デモのプレイヤーが避ける下向きの弾の距離
+const ATTRACT_DANGER_ROWS: i32 = 6;
+
+#[derive(Resource)]
+struct IdleTimer(Timer);
+
+#[derive(Component)]
+struct TitleScreen;
+
+#[derive(Component)]
+struct TitlePrompt;
+
+// タイトル画面とデモ
+// タイトル画面でFireかRestartを押すとゲームが始まり、Tabで操作設定画面、Eでエディタを開く
+// デモ中は何かキーを押すとタイトル画面に戻る
+pub struct TitlePlugin;
+
+impl Plugin for TitlePlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(IdleTimer(Timer::from_seconds(
+            IDLE_SECONDS,
+            TimerMode::Once,
+        )))
+        .add_systems(OnEnter(GameState::Title), (title_enter, reset_idle_timer))
+        .add_systems(OnExit(GameState::Title), title_exit)
+        .add_systems(OnEnter(GameState::GameOver), reset_idle_timer)
+        .add_systems(OnEnter(GameState::Attract), start_attract_timer)
+        .add_systems(
+            Update,
+            (title_system, title_prompt_system).run_if(in_state(GameState::Title)),
+        )
+        .add_systems(
+            Update,
+            idle_system.run_if(in_state(GameState::GameOver).or_else(in_state(GameState::Attract))),
+        )
+        .add_systems(
+            FixedUpdate,
+            attract_intent_system
+                .in_set(InputSet)
+                .run_if(in_state(GameState::Attract)),
+        )
+        .add_systems(
+            Update,
+            attract_exit_system
+                .after(GameplaySet)
+                .run_if(in_state(GameState::Attract)),
+        );
+    }
+}
+
+fn reset_idle_timer(mut idle_timer: ResMut<IdleTimer>) {
+    idle_timer.0 = Timer::from_seconds(IDLE_SECONDS, TimerMode::Once);
+}
+
+fn start_attract_timer(mut idle_timer: ResMut<IdleTimer>) {
+    idle_timer.0 = Timer::from_seconds(ATTRACT_SECONDS, TimerMode::Once);
+}
+
+fn title_enter(
+    mut commands: Commands,
+    hiscores: Res<HiScores>,
+    game: Res<Game>,
+    textures: Res<Textures>,
+    screen: Res<Screen>,
+    query: Query<Entity, With<DespawnOnRestart>>,
+) {
+    // ゲームオーバー画面やデモの残りを消す
+    for entity in &query {
+        commands.entity(entity).despawn_recursive();
+    }
+
+    let mut entities = vec![commands
+        .spawn((
+            DespawnOnRestart,
+            SpriteBundle {
+                texture: textures.title.clone(),
+                transform: position_to_transform(TITLE_POSITION),
+                sprite: create_top_left_sprite(),
+                ..default()
+            },
+        ))
+        .id()];
+
+    let mut texts = vec![
+        ("U 2 3 5".to_string(), 4, Color::YELLOW),
+        (format!("HI-SCORE {}", game.hi_score), 7, Color::WHITE),
+    ];
+    for (i, entry) in hiscores
+        .table
+        .entries
+        .iter()
+        .take(TITLE_HISCORES)
+        .enumerate()
+    {
+        texts.push((
+            format!("{}  {:3}  {:8}", i + 1, entry.name, entry.score),
+            10 + i as i32,
+            Color::WHITE,
+        ));
+    }
+    texts.push((CONTROLS_HELP.to_string(), 21, Color::WHITE));
+    for (text, y, color) in &texts {
+        entities.push(spawn_centered_text(
+            text,
+            *y,
+            *color,
+            &screen,
+            &mut commands,
+        ));
+    }
+    for entity in entities {
+        commands.entity(entity).insert(TitleScreen);
+    }
+
+    let entity = spawn_centered_text(PROMPT, 18, Color::WHITE, &screen, &mut commands);
+    commands.entity(entity).insert((TitleScreen, TitlePrompt));
+}
+
+fn title_exit(mut commands: Commands, query: Query<Entity, With<TitleScreen>>) {
+    for entity in &query {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+fn title_system(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    actions: Res<ActionState>,
+    time: Res<Time>,
+    mut idle_timer: ResMut<IdleTimer>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if actions.just_pressed(Action::Fire) || actions.just_pressed(Action::Restart) {
+        next_state.set(GameState::Playing);
+        return;
+    }
+    if keyboard_input.just_pressed(CONTROLS_KEY) {
+        next_state.set(GameState::Controls);
+        return;
+    }
+    if keyboard_input.just_pressed(EDITOR_KEY) {
+        next_state.set(GameState::Editor);
+        return;
+    }
+    if any_input(&keyboard_input, &actions) {
+        idle_timer.0.reset();
+    }
+    if idle_timer.0.tick(time.delta()).just_finished() {
+        next_state.set(GameState::Attract);
+    }
+}
+
+fn title_prompt_system(time: Res<Time>, mut query: Query<&mut Visibility, With<TitlePrompt>>) {
+    let visibility = if time.elapsed_seconds() % 1.0 < 0.6 {
+        Visibility::Visible
+    } else {
+        Visibility::Hidden
+    };
+    for mut prompt_visibility in &mut query {
+        *prompt_visibility = visibility;
+    }
+}
+
+// キーボードかゲームパッドで何か押されたか
+fn any_input(keyboard_input: &ButtonInput<KeyCode>, actions: &ActionState) -> bool {
+    keyboard_input.get_just_pressed().next().is_some() || actions.any_just_pressed()
+}
+
+// ゲームオーバー画面とデモで時間切れになったらタイトル画面に戻る
+fn idle_system(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    actions: Res<ActionState>,
+    time: Res<Time>,
+    mut idle_timer: ResMut<IdleTimer>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if any_input(&keyboard_input, &actions) {
+        idle_timer.0.reset();
+    }
+    if idle_timer.0.tick(time.delta()).just_finished() {
+        next_state.set(GameState::Title);
+    }
+}
+
+fn attract_exit_system(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    actions: Res<ActionState>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if any_input(&keyboard_input, &actions) {
+        next_state.set(GameState::Title);
+    }
+}
+
+// デモのプレイヤー
+// 近くに落ちてくる弾があれば避け、なければ一番下のターゲットの真下に移動して撃つ
+fn attract_intent_system(
+    mut intent: ResMut<PlayerIntent>,
+    player_query: Query<&Position, With<Player>>,
+    bullet_query: Query<(&Position, &components::Direction), With<Bullet>>,
+    target_query: Query<&Position, With<Target>>,
+    arena: Res<Arena>,
+) {
+    *intent = PlayerIntent::default();
+    let Ok(player) = player_query.get_single() else {
+        return;
+    };
+    // 弾は真ん中から出る
+    let center = player.x + PLAYER_WIDTH / 2;
+
+    let danger = bullet_query
+        .iter()
+        .filter(|(position, direction)| {
+            direction.delta().1 > 0
+                && position.y < player.y
+                && position.y >= player.y - ATTRACT_DANGER_ROWS
+                && (position.x - center).abs() <= 2
+        })
+        .map(|(position, _)| position.x)
+        .next();
+    if let Some(x) = danger {
+        // 壁際では反対側に逃げる
+        let go_right = if arena.is_wall(&player.add(-1, 0)) {
+            true
+        } else if arena.is_wall(&player.add(PLAYER_WIDTH, 0)) {
+            false
+        } else {
+            x <= center
+        };
+        intent.left = !go_right;
+        intent.right = go_right;
+        return;
+    }
+
+    if let Some(target) = target_query.iter().max_by_key(|position| position.y) {
+        intent.left = target.x < center;
+        intent.right = target.x > center;
+        intent.fire = target.x == center;
+    }
+}